@@ -1,16 +1,52 @@
 use axum::{
     http::{HeaderMap, HeaderName, HeaderValue, StatusCode},
     response::Html,
+    Json,
 };
 use chrono::{DateTime, Utc};
 
 pub mod anki;
 pub mod bunpro;
-mod cacheable;
+pub mod cacheable;
+pub mod forecast;
+pub mod history;
+pub mod refresh;
+pub mod registry;
 pub mod satori;
+pub mod study_service;
+pub mod summary;
 pub mod wanikani;
 
 pub type HtmlErrorResponse = (StatusCode, Html<String>);
+pub type JsonErrorResponse = (StatusCode, Json<ApiError>);
+
+/// Envelope for the versioned JSON API, pairing the service data with the
+/// time the underlying cache entries expire
+#[derive(serde::Serialize)]
+pub struct ApiResponse<T> {
+    pub data: T,
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+#[derive(serde::Serialize)]
+pub struct ApiError {
+    pub error: String,
+}
+
+/// A service's session cookie is no longer accepted and needs replacing.
+/// Unlike other errors its message is shown to the user
+#[derive(Debug)]
+pub struct SessionExpired {
+    pub service: &'static str,
+}
+
+impl std::fmt::Display for SessionExpired {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} session expired", self.service)
+    }
+}
+
+impl std::error::Error for SessionExpired {}
 
 pub fn internal_error<E>(err: E) -> HtmlErrorResponse
 where
@@ -21,20 +57,46 @@ where
 
     (
         axum::http::StatusCode::INTERNAL_SERVER_ERROR,
-        Html("Something went wrong".to_string()),
+        Html(user_error_message(&err)),
     )
 }
 
+pub fn json_internal_error<E>(err: E) -> JsonErrorResponse
+where
+    E: Into<anyhow::Error>,
+{
+    let err = err.into();
+    tracing::error!("Error: {}", err.to_string());
+
+    (
+        axum::http::StatusCode::INTERNAL_SERVER_ERROR,
+        Json(ApiError {
+            error: user_error_message(&err),
+        }),
+    )
+}
+
+fn user_error_message(err: &anyhow::Error) -> String {
+    match err.downcast_ref::<SessionExpired>() {
+        Some(session_expired) => session_expired.to_string(),
+        None => "Something went wrong".to_string(),
+    }
+}
+
+pub fn earliest_expiry(expiry_times: &[Option<DateTime<Utc>>]) -> Option<DateTime<Utc>> {
+    expiry_times.iter().flatten().min().copied()
+}
+
 pub fn add_expiry_header(
     header_map: HeaderMap,
     expiry_times: &[Option<DateTime<Utc>>],
 ) -> HeaderMap {
-    let expires_at = expiry_times.iter().flatten().min();
+    let expires_at = earliest_expiry(expiry_times);
 
     let mut header_map = header_map.clone();
 
     if let Some(expires_at) = expires_at {
-        let expiry_header = generate_expiry_header(expires_at);
+        let expiry_header = generate_expiry_header(&expires_at);
         header_map.insert(expiry_header.0, expiry_header.1);
     }
 
@@ -63,6 +125,19 @@ mod test_super {
         assert_eq!(header_value, "Fri, 21 Jun 2024 23:12:00 +0000");
     }
 
+    #[test]
+    fn test_api_response_serializes_data_and_expiry() {
+        let expires_at = Utc.with_ymd_and_hms(2024, 6, 21, 23, 12, 00).unwrap();
+        let response = ApiResponse {
+            data: 5,
+            expires_at: Some(expires_at),
+        };
+
+        let json = serde_json::to_string(&response).unwrap();
+
+        assert_eq!(json, r#"{"data":5,"expires_at":"2024-06-21T23:12:00Z"}"#);
+    }
+
     #[test]
     fn test_add_expiry_header_uses_nearest_expiry() {
         let header_map = HeaderMap::new();
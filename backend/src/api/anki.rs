@@ -2,5 +2,3 @@ pub mod data;
 pub mod request;
 
 mod proto_definitions;
-
-pub use request::anki_handler;
@@ -6,12 +6,14 @@ use super::proto_definitions::DeckInfo;
 #[derive(serde::Serialize, serde::Deserialize, Template)]
 #[template(path = "anki.html")]
 pub struct AnkiData {
-    active_review_count: u32,
+    pub active_review_count: u32,
     total_active_review_count: u32,
-    new_card_count: u32,
+    pub new_card_count: u32,
     total_new_card_count: u32,
     data_updated_at: DateTime<Utc>,
-    daily_study_goal_met: bool,
+    pub daily_study_goal_met: bool,
+    #[serde(skip_deserializing)]
+    pub stale_since: Option<DateTime<Utc>>,
 }
 
 impl From<DeckInfo> for AnkiData {
@@ -23,12 +25,15 @@ impl From<DeckInfo> for AnkiData {
             total_new_card_count: deck.uncapped_new_card_count(),
             data_updated_at: Utc::now(),
             daily_study_goal_met: deck.review_card_count() == 0,
+            stale_since: None,
         }
     }
 }
 
 #[cfg(test)]
 mod test_super {
+    use askama::Template;
+
     use super::*;
 
     fn create_deck_info(
@@ -78,4 +83,15 @@ mod test_super {
         assert_eq!(anki_data.active_review_count, 20);
         assert_eq!(anki_data.new_card_count, 0);
     }
+
+    #[test]
+    fn test_render_shows_stale_since_for_stale_data() {
+        let mut anki_data = AnkiData::from(create_deck_info(Some(10), None, None));
+
+        assert!(!anki_data.render().unwrap().contains("Stale since"));
+
+        anki_data.stale_since = Some(Utc::now());
+
+        assert!(anki_data.render().unwrap().contains("Stale since"));
+    }
 }
@@ -1,18 +1,19 @@
-use std::{env, io::Cursor};
+use std::io::Cursor;
 
 use anyhow::anyhow;
-use askama::Template;
-use axum::{extract::State, http::HeaderMap, response::Html};
 use bytes::Bytes;
-use chrono::{DateTime, Duration, Utc};
+use chrono::{DateTime, Utc};
 use prost::Message;
 use reqwest::Client;
 
-use crate::api::{
-    add_expiry_header,
-    anki::proto_definitions,
-    cacheable::{CacheKey, Cacheable},
-    internal_error, HtmlErrorResponse,
+use crate::{
+    api::{
+        anki::proto_definitions,
+        cacheable::{CacheKey, CacheSource, Cacheable},
+        study_service::{GoalStatus, StudyCounts, StudyService},
+    },
+    config::{credential, AnkiConfig, Config},
+    state::AppState,
 };
 
 use super::{
@@ -20,17 +21,33 @@ use super::{
     proto_definitions::{DeckInfo, DeckListInfo},
 };
 
-pub async fn anki_handler(
-    State(redis_client): State<Option<redis::Client>>,
-) -> Result<(HeaderMap, Html<String>), HtmlErrorResponse> {
-    let (anki_data, cache_expiry_time) =
-        AnkiData::get(&redis_client).await.map_err(internal_error)?;
+impl StudyService for AnkiData {
+    const ID: &'static str = "anki";
+    const DISPLAY_NAME: &'static str = "Anki";
 
-    let headers = add_expiry_header(HeaderMap::new(), &[cache_expiry_time]);
+    fn sources() -> Vec<CacheSource> {
+        vec![CacheSource::of::<AnkiData>()]
+    }
+
+    async fn fetch(state: &AppState) -> anyhow::Result<(Self, Option<DateTime<Utc>>)> {
+        let cached = Self::get(state).await?;
 
-    let html_string = anki_data.render().map_err(internal_error)?;
+        let mut anki_data = cached.data;
+        anki_data.stale_since = cached.stale_since;
 
-    Ok((headers, Html(html_string)))
+        Ok((anki_data, cached.expires_at))
+    }
+
+    fn study_counts(&self) -> StudyCounts {
+        StudyCounts {
+            active_review_count: self.active_review_count,
+            new_item_count: self.new_card_count,
+        }
+    }
+
+    fn goal_status(&self) -> GoalStatus {
+        GoalStatus::from(self.daily_study_goal_met)
+    }
 }
 
 impl Cacheable for AnkiData {
@@ -38,17 +55,13 @@ impl Cacheable for AnkiData {
         CacheKey::Anki
     }
 
-    fn expires_at() -> DateTime<Utc> {
-        Utc::now() + Duration::hours(1)
-    }
-
-    async fn api_fetch() -> anyhow::Result<Self> {
-        Ok(Self::from(get_decks_data().await?))
+    async fn api_fetch(config: &Config) -> anyhow::Result<Self> {
+        Ok(Self::from(get_decks_data(&config.anki).await?))
     }
 }
 
-async fn get_decks_data() -> anyhow::Result<DeckInfo> {
-    let cookie = env::var("ANKIWEB_COOKIE")?;
+async fn get_decks_data(config: &AnkiConfig) -> anyhow::Result<DeckInfo> {
+    let cookie = credential(&config.cookie, "AnkiWeb cookie")?;
 
     let encoded_message = Client::new()
         .post("https://ankiweb.net/svc/decks/deck-list-info")
@@ -62,10 +75,10 @@ async fn get_decks_data() -> anyhow::Result<DeckInfo> {
 
     let deck_list_info = decode_protobuf_response(encoded_message)?;
 
-    let japanese_deck =
-        get_japanese_deck(&deck_list_info).ok_or(anyhow!("Could not find Japanese deck"))?;
+    let deck = get_deck(&deck_list_info, &config.deck_name)
+        .ok_or(anyhow!("Could not find {} deck", config.deck_name))?;
 
-    Ok(japanese_deck)
+    Ok(deck)
 }
 
 fn decode_protobuf_response(encoded_message: Bytes) -> anyhow::Result<DeckListInfo> {
@@ -74,14 +87,14 @@ fn decode_protobuf_response(encoded_message: Bytes) -> anyhow::Result<DeckListIn
     ))?)
 }
 
-fn get_japanese_deck(deck_list_info: &DeckListInfo) -> Option<DeckInfo> {
+fn get_deck(deck_list_info: &DeckListInfo, deck_name: &str) -> Option<DeckInfo> {
     // TODO: refactor to remove clone
     deck_list_info
         .all_decks_info
         .clone()?
         .decks
         .into_iter()
-        .find(|deck| deck.deck_name == "Japanese")
+        .find(|deck| deck.deck_name == deck_name)
 }
 
 #[cfg(test)]
@@ -1,4 +1,2 @@
 pub mod data;
 pub mod request;
-
-pub use request::bunpro_handler;
@@ -1,12 +1,11 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 
 use askama::Template;
 use chrono::{DateTime, NaiveDate, Utc};
 use chrono_tz::Asia::Tokyo;
 use serde::{Deserialize, Serialize};
 
-// TODO: Add custom deserialization for Epoch timestamp in seconds
-// to DateTime<Utc> type
+use crate::{api::history::StudyHistory, config::BunproGoalDecks};
 
 #[derive(Debug, Deserialize, Serialize)]
 pub struct StudyQueue {
@@ -15,23 +14,53 @@ pub struct StudyQueue {
     pub fetched_at: Option<DateTime<Utc>>,
 }
 
+impl StudyQueue {
+    /// Bunpro sends a zero timestamp when nothing is scheduled
+    pub fn next_review_at(&self) -> Option<DateTime<Utc>> {
+        Some(self.requested_information.next_review_date).filter(|at| at.timestamp() > 0)
+    }
+}
+
 #[derive(Serialize, Deserialize, Template)]
 #[template(path = "bunpro.html")]
 pub struct BunproData {
     data_updated_at: DateTime<Utc>,
-    active_review_count: u32,
-    daily_study_goal_met: bool,
+    pub active_review_count: u32,
+    pub next_review_at: Option<DateTime<Utc>>,
+    pub reviews_available_next_hour: u32,
+    pub reviews_available_next_day: u32,
+    pub ghost_review_count: u32,
+    pub grammar_point_count: u32,
+    pub todays_reviews: DeckCounts,
+    pub daily_study_goal_met: bool,
+    #[serde(skip_deserializing)]
+    pub stale_since: Option<DateTime<Utc>>,
 }
 
 impl BunproData {
-    pub fn new(study_queue: StudyQueue, stats: BunproReviewStats) -> Self {
+    pub fn new(
+        study_queue: StudyQueue,
+        stats: BunproReviewStats,
+        goal_decks: BunproGoalDecks,
+    ) -> Self {
         let today = Utc::now().with_timezone(&Tokyo);
-        let todays_stats = stats.count_for(today.naive_local().date());
+        let todays_reviews = stats.counts_for(today.naive_local().date());
 
         Self {
             data_updated_at: study_queue.fetched_at.unwrap_or(Utc::now()),
             active_review_count: study_queue.requested_information.reviews_available,
-            daily_study_goal_met: todays_stats > 0,
+            next_review_at: study_queue.next_review_at(),
+            reviews_available_next_hour: study_queue
+                .requested_information
+                .reviews_available_next_hour,
+            reviews_available_next_day: study_queue
+                .requested_information
+                .reviews_available_next_day,
+            ghost_review_count: study_queue.user_information.ghost_review_count,
+            grammar_point_count: study_queue.user_information.grammar_point_count,
+            todays_reviews,
+            daily_study_goal_met: todays_reviews.goal_met(goal_decks),
+            stale_since: None,
         }
     }
 }
@@ -41,13 +70,15 @@ struct UserInformation {
     username: String,
     grammar_point_count: u32,
     ghost_review_count: u32,
-    creation_date: u32,
+    #[serde(with = "chrono::serde::ts_seconds")]
+    creation_date: DateTime<Utc>,
 }
 
 #[derive(Debug, Deserialize, Serialize)]
 struct StudyQueueData {
     reviews_available: u32,
-    next_review_date: u32,
+    #[serde(with = "chrono::serde::ts_seconds")]
+    next_review_date: DateTime<Utc>,
     reviews_available_next_hour: u32,
     reviews_available_next_day: u32,
 }
@@ -58,13 +89,54 @@ pub struct BunproReviewStats {
     vocab: HashMap<String, u32>,
 }
 
+/// Reviews done in each deck on one day
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+pub struct DeckCounts {
+    pub grammar: u32,
+    pub vocab: u32,
+}
+
+impl DeckCounts {
+    pub fn goal_met(&self, goal_decks: BunproGoalDecks) -> bool {
+        match goal_decks {
+            BunproGoalDecks::Grammar => self.grammar > 0,
+            BunproGoalDecks::Vocab => self.vocab > 0,
+            BunproGoalDecks::Any => self.grammar > 0 || self.vocab > 0,
+            BunproGoalDecks::All => self.grammar > 0 && self.vocab > 0,
+        }
+    }
+}
+
 impl BunproReviewStats {
-    pub fn count_for(self, date: NaiveDate) -> u32 {
+    pub fn counts_for(&self, date: NaiveDate) -> DeckCounts {
         let date_string = date.format("%Y-%m-%d").to_string();
 
-        self.grammar
-            .iter()
-            .find_map(|(k, v)| if k == &date_string { Some(*v) } else { None })
-            .unwrap_or(0)
+        DeckCounts {
+            grammar: self.grammar.get(&date_string).copied().unwrap_or(0),
+            vocab: self.vocab.get(&date_string).copied().unwrap_or(0),
+        }
     }
+
+    /// Reviews across both decks for every day in the history
+    pub fn history(&self, today: NaiveDate) -> StudyHistory {
+        let mut daily_counts = BTreeMap::new();
+
+        for (date_string, count) in self.grammar.iter().chain(&self.vocab) {
+            let Ok(date) = NaiveDate::parse_from_str(date_string, "%Y-%m-%d") else {
+                continue;
+            };
+
+            *daily_counts.entry(date).or_insert(0) += count;
+        }
+
+        StudyHistory::new(daily_counts, today)
+    }
+}
+
+#[derive(Serialize, Template)]
+#[template(path = "bunpro_history.html")]
+pub struct BunproHistory {
+    #[serde(flatten)]
+    pub history: StudyHistory,
+    pub stale_since: Option<DateTime<Utc>>,
 }
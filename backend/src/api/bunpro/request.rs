@@ -1,37 +1,64 @@
-use std::env;
-
-use askama::Template;
-use axum::{extract::State, http::HeaderMap, response::Html};
-use chrono::{DateTime, Duration, Utc};
+use axum::{routing::get, Router};
+use chrono::{DateTime, Utc};
 use reqwest::Client;
 use tokio::try_join;
 
-use crate::api::{
-    add_expiry_header,
-    bunpro::data::BunproReviewStats,
-    cacheable::{CacheKey, Cacheable},
-    internal_error, HtmlErrorResponse,
+use crate::{
+    api::{
+        bunpro::data::BunproReviewStats,
+        cacheable::{earliest_stale_since, expires_after, CacheKey, CacheSource, Cacheable},
+        earliest_expiry,
+        study_service::{GoalStatus, StudyCounts, StudyService},
+    },
+    config::{credential, Config},
+    state::AppState,
 };
 
 use super::data::{BunproData, StudyQueue};
 
+mod history;
 mod stats;
 
-pub async fn bunpro_handler(
-    State(redis_client): State<Option<redis::Client>>,
-) -> Result<(HeaderMap, Html<String>), HtmlErrorResponse> {
-    let ((study_queue_data, study_queue_expiry), (stats_data, stats_expiry)) = try_join!(
-        StudyQueue::get(&redis_client),
-        BunproReviewStats::get(&redis_client)
-    )
-    .map_err(internal_error)?;
+impl StudyService for BunproData {
+    const ID: &'static str = "bunpro";
+    const DISPLAY_NAME: &'static str = "Bunpro";
+
+    fn sources() -> Vec<CacheSource> {
+        vec![
+            CacheSource::of::<StudyQueue>(),
+            CacheSource::of::<BunproReviewStats>(),
+        ]
+    }
 
-    let bunpro_data = BunproData::new(study_queue_data, stats_data);
+    fn extra_routes(router: Router<AppState>) -> Router<AppState> {
+        router
+            .route("/bunpro/history", get(history::history_handler))
+            .route("/api/v1/bunpro/history", get(history::history_json_handler))
+    }
 
-    let headers = add_expiry_header(HeaderMap::new(), &[study_queue_expiry, stats_expiry]);
-    let html_string = bunpro_data.render().map_err(internal_error)?;
+    async fn fetch(state: &AppState) -> anyhow::Result<(Self, Option<DateTime<Utc>>)> {
+        let (study_queue, stats) =
+            try_join!(StudyQueue::get(state), BunproReviewStats::get(state))?;
+
+        let mut bunpro_data =
+            BunproData::new(study_queue.data, stats.data, state.config.bunpro.goal_decks);
+        bunpro_data.stale_since =
+            earliest_stale_since(&[study_queue.stale_since, stats.stale_since]);
+        let expires_at = earliest_expiry(&[study_queue.expires_at, stats.expires_at]);
+
+        Ok((bunpro_data, expires_at))
+    }
 
-    Ok((headers, Html(html_string)))
+    fn study_counts(&self) -> StudyCounts {
+        StudyCounts {
+            active_review_count: self.active_review_count,
+            new_item_count: 0,
+        }
+    }
+
+    fn goal_status(&self) -> GoalStatus {
+        GoalStatus::from(self.daily_study_goal_met)
+    }
 }
 
 impl Cacheable for StudyQueue {
@@ -39,12 +66,8 @@ impl Cacheable for StudyQueue {
         CacheKey::Bunpro
     }
 
-    fn expires_at() -> DateTime<Utc> {
-        Utc::now() + Duration::hours(1)
-    }
-
-    async fn api_fetch() -> anyhow::Result<Self> {
-        let bunpro_api_token = env::var("BUNPRO_API_TOKEN")?;
+    async fn api_fetch(config: &Config) -> anyhow::Result<Self> {
+        let bunpro_api_token = credential(&config.bunpro.api_token, "Bunpro API token")?;
         let url = format!("https://bunpro.jp/api/user/{bunpro_api_token}/study_queue");
 
         let study_queue = Client::new()
@@ -59,6 +82,10 @@ impl Cacheable for StudyQueue {
 
         Ok(study_queue)
     }
+
+    fn expires_at(&self, config: &Config) -> DateTime<Utc> {
+        expires_after(self.next_review_at(), config)
+    }
 }
 
 fn serialize_response(body: &str) -> anyhow::Result<StudyQueue> {
@@ -72,6 +99,7 @@ fn serialize_response(body: &str) -> anyhow::Result<StudyQueue> {
 #[cfg(test)]
 mod test_super {
     use super::*;
+    use crate::config::BunproGoalDecks;
 
     #[test]
     fn test_bunpro_with_reviews() {
@@ -80,6 +108,37 @@ mod test_super {
         assert!(response.is_ok());
     }
 
+    #[test]
+    fn test_bunpro_next_review_at() {
+        let with_reviews = include_str!("./fixtures/bunpro_with_reviews.json");
+        let study_queue = serialize_response(with_reviews).unwrap();
+
+        assert_eq!(
+            study_queue.next_review_at(),
+            Some("2023-07-01T12:00:00Z".parse().unwrap())
+        );
+    }
+
+    #[test]
+    fn test_bunpro_data_keeps_upcoming_reviews() {
+        let with_reviews = include_str!("./fixtures/bunpro_with_reviews.json");
+        let study_queue = serialize_response(with_reviews).unwrap();
+
+        let history = include_str!("./fixtures/bunpro_review_history.json");
+        let stats = stats::serialize_stats_response(history.to_string()).unwrap();
+
+        let bunpro_data = BunproData::new(study_queue, stats, BunproGoalDecks::Any);
+
+        assert_eq!(
+            bunpro_data.next_review_at,
+            Some("2023-07-01T12:00:00Z".parse().unwrap())
+        );
+        assert_eq!(bunpro_data.reviews_available_next_hour, 26);
+        assert_eq!(bunpro_data.reviews_available_next_day, 32);
+        assert_eq!(bunpro_data.ghost_review_count, 3623);
+        assert_eq!(bunpro_data.grammar_point_count, 792);
+    }
+
     #[test]
     fn test_bunpro_with_no_reviews() {
         let with_no_reviews = include_str!("./fixtures/bunpro_with_no_reviews.json");
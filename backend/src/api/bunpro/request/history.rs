@@ -0,0 +1,55 @@
+use askama::Template;
+use axum::{extract::State, http::HeaderMap, response::Html, Json};
+use chrono::Utc;
+use chrono_tz::Asia::Tokyo;
+
+use crate::{
+    api::{
+        add_expiry_header,
+        bunpro::data::{BunproHistory, BunproReviewStats},
+        cacheable::{Cacheable, Cached},
+        internal_error, json_internal_error, ApiResponse, HtmlErrorResponse, JsonErrorResponse,
+    },
+    state::AppState,
+};
+
+pub async fn history_handler(
+    State(state): State<AppState>,
+) -> Result<(HeaderMap, Html<String>), HtmlErrorResponse> {
+    let cached = BunproReviewStats::get(&state)
+        .await
+        .map_err(internal_error)?;
+
+    let headers = add_expiry_header(HeaderMap::new(), &[cached.expires_at]);
+    let html_string = build_history(cached).render().map_err(internal_error)?;
+
+    Ok((headers, Html(html_string)))
+}
+
+pub async fn history_json_handler(
+    State(state): State<AppState>,
+) -> Result<(HeaderMap, Json<ApiResponse<BunproHistory>>), JsonErrorResponse> {
+    let cached = BunproReviewStats::get(&state)
+        .await
+        .map_err(json_internal_error)?;
+
+    let expires_at = cached.expires_at;
+    let headers = add_expiry_header(HeaderMap::new(), &[expires_at]);
+
+    Ok((
+        headers,
+        Json(ApiResponse {
+            data: build_history(cached),
+            expires_at,
+        }),
+    ))
+}
+
+fn build_history(cached: Cached<BunproReviewStats>) -> BunproHistory {
+    let today = Utc::now().with_timezone(&Tokyo).date_naive();
+
+    BunproHistory {
+        history: cached.data.history(today),
+        stale_since: cached.stale_since,
+    }
+}
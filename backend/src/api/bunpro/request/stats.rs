@@ -1,53 +1,116 @@
-use std::env;
+use std::time::{Duration as StdDuration, SystemTime};
 
-use crate::api::{
-    bunpro::data::BunproReviewStats,
-    cacheable::{CacheKey, Cacheable},
+use crate::{
+    api::{
+        bunpro::data::BunproReviewStats,
+        cacheable::{backend::CacheBackend, expires_after, next_jst_midnight, CacheKey, Cacheable},
+        SessionExpired,
+    },
+    config::{credential, BunproConfig, Config},
+    state::AppState,
 };
 use anyhow::anyhow;
 use chrono::{DateTime, Duration, Utc};
-use reqwest::{header, Client};
+use reqwest::{header, Client, StatusCode};
+
+const REVIEW_ACTIVITY_URL: &str = "https://bunpro.jp/api/frontend/user_stats/review_activity";
+/// How long to keep the token when its cookie doesn't say when it expires
+const FRONTEND_TOKEN_TTL_HOURS: i64 = 24;
 
 impl Cacheable for BunproReviewStats {
     fn cache_key() -> CacheKey {
         CacheKey::BunproStats
     }
 
-    fn expires_at() -> DateTime<Utc> {
-        Utc::now() + Duration::hours(1)
+    async fn api_fetch(config: &Config) -> anyhow::Result<Self> {
+        let token = get_frontend_auth_token(&config.bunpro).await?;
+
+        fetch_review_activity(&token.value)
+            .await?
+            .ok_or(anyhow!("Bunpro rejected a newly issued frontend api token"))
     }
 
-    async fn api_fetch() -> anyhow::Result<Self> {
-        let frontend_session_cookie = get_frontend_auth_token().await?;
-        let client = bunpro_stats_client(frontend_session_cookie)?;
+    async fn api_update(state: &AppState, _previous: Option<Self>) -> anyhow::Result<Self> {
+        if let Some(token) = cached_frontend_token(&state.cache).await {
+            if let Some(stats) = fetch_review_activity(&token).await? {
+                return Ok(stats);
+            }
 
-        client
-            .get("https://bunpro.jp/api/frontend/user_stats/review_activity")
-            .send()
+            tracing::info!("Cached Bunpro frontend api token was rejected, fetching a new one");
+        }
+
+        let token = get_frontend_auth_token(&state.config.bunpro).await?;
+        let stats = fetch_review_activity(&token.value)
             .await?
-            .error_for_status()?
-            .text()
-            .await
-            .map(serialize_stats_response)?
+            .ok_or(anyhow!("Bunpro rejected a newly issued frontend api token"))?;
+
+        let cache_result = state
+            .cache
+            .set(
+                &CacheKey::BunproFrontendToken.to_string(),
+                token.value,
+                token.expires_at,
+            )
+            .await;
+
+        if let Err(err) = cache_result {
+            tracing::warn!("Failed to cache Bunpro frontend api token: {err:#}");
+        }
+
+        Ok(stats)
+    }
+
+    fn expires_at(&self, config: &Config) -> DateTime<Utc> {
+        expires_after(Some(next_jst_midnight(Utc::now())), config)
     }
 }
 
-fn bunpro_stats_client(frontend_session_token: String) -> anyhow::Result<Client> {
+/// Fetches the review activity, or `None` when the token is rejected
+async fn fetch_review_activity(frontend_token: &str) -> anyhow::Result<Option<BunproReviewStats>> {
+    let response = bunpro_stats_client(frontend_token)?
+        .get(REVIEW_ACTIVITY_URL)
+        .send()
+        .await?;
+
+    if response.status() == StatusCode::UNAUTHORIZED {
+        return Ok(None);
+    }
+
+    let body = response.error_for_status()?.text().await?;
+
+    serialize_stats_response(body).map(Some)
+}
+
+async fn cached_frontend_token(cache: &CacheBackend) -> Option<String> {
+    cache
+        .get(&CacheKey::BunproFrontendToken.to_string())
+        .await
+        .unwrap_or_else(|err| {
+            tracing::warn!("Failed to read cached Bunpro frontend api token: {err:#}");
+
+            None
+        })
+}
+
+fn bunpro_stats_client(frontend_session_token: &str) -> anyhow::Result<Client> {
     let mut headers = reqwest::header::HeaderMap::new();
     headers.insert(
         header::AUTHORIZATION,
-        format!("Token token={}", frontend_session_token)
-            .parse()
-            .unwrap(),
+        format!("Token token={}", frontend_session_token).parse()?,
     );
 
     Ok(Client::builder().default_headers(headers).build()?)
 }
 
-async fn get_frontend_auth_token() -> anyhow::Result<String> {
+struct FrontendToken {
+    value: String,
+    expires_at: DateTime<Utc>,
+}
+
+async fn get_frontend_auth_token(config: &BunproConfig) -> anyhow::Result<FrontendToken> {
     const TOKEN_NAME: &str = "frontend_api_token";
 
-    let bunpro_grammar_cookie = env::var("BUNPRO_GRAMMAR_COOKIE")?;
+    let bunpro_grammar_cookie = credential(&config.grammar_cookie, "Bunpro grammar cookie")?;
 
     let mut headers = reqwest::header::HeaderMap::new();
     headers.insert(
@@ -66,18 +129,39 @@ async fn get_frontend_auth_token() -> anyhow::Result<String> {
         .await?
         .error_for_status()?;
 
-    let cookie = bunpro_login.cookies().find_map(|cookie| {
-        if cookie.name() == TOKEN_NAME {
-            Some(cookie.value().to_string())
-        } else {
-            None
-        }
-    });
+    let token = bunpro_login
+        .cookies()
+        .find(|cookie| cookie.name() == TOKEN_NAME)
+        .map(|cookie| FrontendToken {
+            value: cookie.value().to_string(),
+            expires_at: token_expiry(cookie.max_age(), cookie.expires(), Utc::now()),
+        });
+
+    // the token is only handed out to a logged in session
+    token.ok_or_else(|| {
+        tracing::error!(
+            "Bunpro _grammar_app_session cookie has expired, the grammar cookie needs replacing"
+        );
 
-    cookie.ok_or(anyhow!(format!("{} cookie could not be found", TOKEN_NAME)))
+        SessionExpired { service: "Bunpro" }.into()
+    })
 }
 
-fn serialize_stats_response(body: String) -> anyhow::Result<BunproReviewStats> {
+fn token_expiry(
+    max_age: Option<StdDuration>,
+    expires: Option<SystemTime>,
+    now: DateTime<Utc>,
+) -> DateTime<Utc> {
+    let max_age_expiry = max_age
+        .and_then(|max_age| Duration::from_std(max_age).ok())
+        .map(|max_age| now + max_age);
+
+    max_age_expiry
+        .or(expires.map(DateTime::<Utc>::from))
+        .unwrap_or(now + Duration::hours(FRONTEND_TOKEN_TTL_HOURS))
+}
+
+pub(super) fn serialize_stats_response(body: String) -> anyhow::Result<BunproReviewStats> {
     let json = serde_json::from_str(&body)?;
 
     Ok(json)
@@ -88,6 +172,7 @@ mod test_super {
     use chrono::NaiveDate;
 
     use super::*;
+    use crate::{api::bunpro::data::DeckCounts, config::BunproGoalDecks};
 
     #[test]
     fn test_serialize_stats_response() {
@@ -97,8 +182,14 @@ mod test_super {
         assert!(stats.is_ok());
 
         let date = NaiveDate::parse_from_str("2023-09-30", "%Y-%m-%d").unwrap();
-        let count_for = stats.unwrap().count_for(date);
-        assert_eq!(count_for, 21);
+        let counts = stats.unwrap().counts_for(date);
+        assert_eq!(
+            counts,
+            DeckCounts {
+                grammar: 21,
+                vocab: 0
+            }
+        );
     }
 
     #[test]
@@ -109,7 +200,60 @@ mod test_super {
         assert!(stats.is_ok());
 
         let date = NaiveDate::parse_from_str("2023-01-01", "%Y-%m-%d").unwrap();
-        let count_for = stats.unwrap().count_for(date);
-        assert_eq!(count_for, 0);
+        let counts = stats.unwrap().counts_for(date);
+        assert_eq!(counts, DeckCounts::default());
+    }
+
+    #[test]
+    fn test_token_expiry_prefers_max_age() {
+        let now = "2023-10-01T00:00:00Z".parse().unwrap();
+        let expires = SystemTime::from("2023-10-03T00:00:00Z".parse::<DateTime<Utc>>().unwrap());
+
+        assert_eq!(
+            token_expiry(Some(StdDuration::from_secs(3600)), Some(expires), now),
+            now + Duration::hours(1)
+        );
+        assert_eq!(
+            token_expiry(None, Some(expires), now),
+            DateTime::<Utc>::from(expires)
+        );
+        assert_eq!(
+            token_expiry(None, None, now),
+            now + Duration::hours(FRONTEND_TOKEN_TTL_HOURS)
+        );
+    }
+
+    #[test]
+    fn test_history_combines_decks() {
+        let stats = serialize_stats_response(
+            r#"{
+                "grammar": { "2023-10-01": 5, "2023-10-02": 0, "2023-10-03": 2 },
+                "vocab": { "2023-10-01": 1, "2023-10-02": 4 }
+            }"#
+            .to_string(),
+        )
+        .unwrap();
+
+        let today = NaiveDate::parse_from_str("2023-10-03", "%Y-%m-%d").unwrap();
+        let history = stats.history(today);
+
+        assert_eq!(history.daily_counts.values().sum::<u32>(), 12);
+        assert_eq!(history.current_streak, 3);
+    }
+
+    #[test]
+    fn test_vocab_reviews_count_toward_goal() {
+        let stats = serialize_stats_response(
+            r#"{ "grammar": { "2023-10-01": 0 }, "vocab": { "2023-10-01": 12 } }"#.to_string(),
+        )
+        .unwrap();
+
+        let date = NaiveDate::parse_from_str("2023-10-01", "%Y-%m-%d").unwrap();
+        let counts = stats.counts_for(date);
+
+        assert!(counts.goal_met(BunproGoalDecks::Any));
+        assert!(counts.goal_met(BunproGoalDecks::Vocab));
+        assert!(!counts.goal_met(BunproGoalDecks::Grammar));
+        assert!(!counts.goal_met(BunproGoalDecks::All));
     }
 }
@@ -1,16 +1,44 @@
-use std::fmt::Display;
+use std::{fmt::Display, future::Future, pin::Pin, time::Duration as StdDuration};
 
-use anyhow::anyhow;
-use chrono::{DateTime, Duration, Utc};
-use redis::{AsyncCommands, SetOptions, ToRedisArgs};
+use chrono::{DateTime, Datelike, Duration, TimeZone, Utc};
+use chrono_tz::Asia::Tokyo;
+use rand::Rng;
 use serde::de::DeserializeOwned;
-use tokio::sync::Mutex;
 
+use crate::{config::Config, state::AppState};
+
+use backend::CacheBackend;
+
+pub mod backend;
+pub mod single_flight;
+
+type RefreshFuture = Pin<Box<dyn Future<Output = anyhow::Result<()>> + Send>>;
+
+/// How often callers waiting on another instance's refresh re-check the cache
+const LOCK_POLL_INTERVAL: StdDuration = StdDuration::from_millis(250);
+
+/// Time after a failed background refresh before stale reads try again, so
+/// a failing upstream isn't hit on every page load
+const FAILED_REFRESH_COOLDOWN_SECONDS: i64 = 60;
+
+/// Time after an upstream change before expiring, so the API has caught up
+const UPSTREAM_DELAY_SECONDS: i64 = 30;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
 pub enum CacheKey {
     WanikaniSummary,
     WanikaniStats,
+    WanikaniLessonStats,
+    WanikaniUser,
+    WanikaniLevelProgress,
+    WanikaniAssignments,
+    WanikaniReviewStatistics,
+    WanikaniLeeches,
     Bunpro,
     BunproStats,
+    /// The frontend token is cached separately from the stats, so it's
+    /// reused across refreshes instead of logging in every time
+    BunproFrontendToken,
     SatoriReviewCards,
     SatoriNewCards,
     SatoriStats,
@@ -22,8 +50,15 @@ impl Display for CacheKey {
         let cache_key = match self {
             CacheKey::WanikaniSummary => "wanikani_summary_data",
             CacheKey::WanikaniStats => "wanikani_stats_data",
+            CacheKey::WanikaniLessonStats => "wanikani_lesson_stats",
+            CacheKey::WanikaniUser => "wanikani_user",
+            CacheKey::WanikaniLevelProgress => "wanikani_level_progress",
+            CacheKey::WanikaniAssignments => "wanikani_assignments",
+            CacheKey::WanikaniReviewStatistics => "wanikani_review_statistics",
+            CacheKey::WanikaniLeeches => "wanikani_leeches",
             CacheKey::Bunpro => "bunpro_data",
             CacheKey::BunproStats => "bunpro_stats",
+            CacheKey::BunproFrontendToken => "bunpro_frontend_token",
             CacheKey::SatoriReviewCards => "satori_review_cards",
             CacheKey::SatoriNewCards => "satori_new_cards",
             CacheKey::SatoriStats => "satori_stats",
@@ -34,109 +69,592 @@ impl Display for CacheKey {
     }
 }
 
-impl ToRedisArgs for CacheKey {
-    fn write_redis_args<W>(&self, out: &mut W)
-    where
-        W: ?Sized + redis::RedisWrite,
-    {
-        out.write_arg_fmt(self);
-    }
+/// Value returned from the cache, along with when it needs refreshing. A
+/// value past its freshness window is still served, with `stale_since` set
+pub struct Cached<T> {
+    pub data: T,
+    pub expires_at: Option<DateTime<Utc>>,
+    pub stale_since: Option<DateTime<Utc>>,
+}
+
+/// What's actually written to the cache backend. The key outlives `fresh_until` by
+/// the stale retention period so the last good value is kept around
+#[derive(serde::Serialize, serde::Deserialize)]
+struct CacheEntry<T> {
+    data: T,
+    fresh_until: DateTime<Utc>,
 }
 
-pub trait Cacheable: DeserializeOwned + serde::Serialize {
+pub trait Cacheable: DeserializeOwned + serde::Serialize + Send + Sync + 'static {
     fn cache_key() -> CacheKey;
-    fn expires_at() -> DateTime<Utc>;
-    async fn api_fetch() -> anyhow::Result<Self>;
+    fn api_fetch(config: &Config) -> impl Future<Output = anyhow::Result<Self>> + Send;
+
+    /// Whether the value is synced incrementally, so the currently cached
+    /// value is passed to `api_update` when refreshing
+    const INCREMENTAL: bool = false;
+
+    /// Fetches the latest value given the cached one, which is only read
+    /// when `INCREMENTAL` is set. Also has the app state, for types that keep
+    /// values like auth tokens in the cache between fetches
+    fn api_update(
+        state: &AppState,
+        previous: Option<Self>,
+    ) -> impl Future<Output = anyhow::Result<Self>> + Send {
+        drop(previous);
+
+        Self::api_fetch(&state.config)
+    }
 
-    async fn get(
-        redis_client: &Option<redis::Client>,
-    ) -> anyhow::Result<(Self, Option<DateTime<Utc>>)> {
-        let cache_data = Self::cache_read(redis_client).await;
-        let expires_at = Self::get_expiry_time(redis_client).await;
+    /// When the fetched data should be refetched. Defaults to the ttl, types
+    /// that know when their data next changes should use `expires_after`
+    fn expires_at(&self, config: &Config) -> DateTime<Utc> {
+        Utc::now() + config.cache.ttl()
+    }
+
+    fn get(state: &AppState) -> impl Future<Output = anyhow::Result<Cached<Self>>> + Send {
+        async move {
+            let cache_entry = cache_read::<Self>(&state.cache).await;
+
+            match cache_entry {
+                Some(entry) if entry.fresh_until > Utc::now() => Ok(Cached {
+                    data: entry.data,
+                    expires_at: Some(entry.fresh_until),
+                    stale_since: None,
+                }),
+                Some(entry) => {
+                    spawn_background_refresh::<Self>(state);
 
-        if let Some(cache_data) = cache_data {
-            return Ok((cache_data, expires_at));
+                    // expire immediately so browsers pick up the refreshed value
+                    Ok(Cached {
+                        data: entry.data,
+                        expires_at: Some(Utc::now()),
+                        stale_since: Some(entry.fresh_until),
+                    })
+                }
+                None => {
+                    let (data, expires_at) = refresh_once::<Self>(state).await?;
+
+                    Ok(Cached {
+                        data,
+                        expires_at: Some(expires_at),
+                        stale_since: None,
+                    })
+                }
+            }
         }
+    }
 
-        let api_data = Self::api_fetch().await?;
-        let api_data = Mutex::new(api_data);
+    /// Fetches from the api and overwrites the cached value, regardless of
+    /// whether the current value is still fresh
+    fn refresh(
+        state: &AppState,
+    ) -> impl Future<Output = anyhow::Result<(Self, DateTime<Utc>)>> + Send {
+        async move {
+            // stale values are still a good base to sync from
+            let previous = match Self::INCREMENTAL {
+                true => cache_read::<Self>(&state.cache)
+                    .await
+                    .map(|entry| entry.data),
+                false => None,
+            };
 
-        let write_result = Self::cache_write(redis_client, &api_data).await;
+            let api_data = Self::api_update(state, previous).await?;
+            let expires_at = api_data.expires_at(&state.config);
 
-        let _ = write_result.map_err(Self::cache_log);
+            let entry = CacheEntry {
+                data: api_data,
+                fresh_until: expires_at,
+            };
+            let retain_until = expires_at + state.config.cache.stale_retention();
 
-        Ok((api_data.into_inner(), Some(Self::expires_at())))
+            let write_result =
+                cache_write(&state.cache, Self::cache_key(), &entry, retain_until).await;
+
+            let _ = write_result.map_err(cache_log);
+
+            Ok((entry.data, expires_at))
+        }
     }
+}
 
-    async fn get_expiry_time(redis_client: &Option<redis::Client>) -> Option<DateTime<Utc>> {
-        let mut conn = redis_client
-            .as_ref()?
-            .get_multiplexed_tokio_connection()
-            .await
-            .map_err(Self::cache_log)
-            .ok()?;
+/// A `Cacheable` type paired with its key, so that sources of different types
+/// can be stored together and refreshed without knowing the type
+#[derive(Clone, Copy)]
+pub struct CacheSource {
+    pub cache_key: CacheKey,
+    refresh: fn(AppState) -> RefreshFuture,
+}
 
-        let request_time = Utc::now();
+impl CacheSource {
+    pub fn of<T: Cacheable>() -> Self {
+        Self {
+            cache_key: T::cache_key(),
+            refresh: refresh_source::<T>,
+        }
+    }
 
-        let remaining_ttl: Option<i64> = conn
-            .ttl(Self::cache_key())
-            .await
-            .map_err(Self::cache_log)
-            .ok();
+    pub fn refresh(&self, state: AppState) -> RefreshFuture {
+        (self.refresh)(state)
+    }
+}
+
+fn refresh_source<T: Cacheable>(state: AppState) -> RefreshFuture {
+    Box::pin(async move {
+        // forced refreshes still wait for any in-flight fetch of the key
+        let _guard = state.single_flight.acquire(T::cache_key()).await;
+
+        T::refresh(&state).await.map(|_| ())
+    })
+}
+
+/// Expiry just after `next_change`, when the upstream data is known to change,
+/// but no later than the configured ttl
+pub fn expires_after(next_change: Option<DateTime<Utc>>, config: &Config) -> DateTime<Utc> {
+    expiry_between(Utc::now(), next_change, config.cache.ttl())
+}
+
+fn expiry_between(
+    now: DateTime<Utc>,
+    next_change: Option<DateTime<Utc>>,
+    ttl: Duration,
+) -> DateTime<Utc> {
+    let latest = now + ttl;
+
+    match next_change {
+        Some(next_change) if next_change > now => {
+            (next_change + Duration::seconds(UPSTREAM_DELAY_SECONDS)).min(latest)
+        }
+        _ => latest,
+    }
+}
+
+/// Start of the next day in Japan, when daily study goals reset
+pub fn next_jst_midnight(now: DateTime<Utc>) -> DateTime<Utc> {
+    let tomorrow = now.with_timezone(&Tokyo).date_naive() + Duration::days(1);
+
+    Tokyo
+        .with_ymd_and_hms(tomorrow.year(), tomorrow.month(), tomorrow.day(), 0, 0, 0)
+        .unwrap()
+        .with_timezone(&Utc)
+}
+
+/// Earliest of the given times, used to combine the staleness of the
+/// `Cacheable` values that make up a service
+pub fn earliest_stale_since(stale_times: &[Option<DateTime<Utc>>]) -> Option<DateTime<Utc>> {
+    stale_times.iter().flatten().min().copied()
+}
+
+/// Refreshes a stale value without waiting on it. Skipped while a fetch of
+/// the key is already running, or shortly after one failed
+fn spawn_background_refresh<T: Cacheable>(state: &AppState) {
+    let cache_key = T::cache_key();
+    let cooldown = Duration::seconds(FAILED_REFRESH_COOLDOWN_SECONDS);
+
+    if state
+        .single_flight
+        .failed_within(cache_key, cooldown, Utc::now())
+    {
+        return;
+    }
+
+    // taken before spawning, so reads racing this one see the fetch running
+    let Some(guard) = state.single_flight.try_acquire(cache_key) else {
+        return;
+    };
+
+    let refresh_state = state.clone();
+    tokio::spawn(async move {
+        match refresh_once_locked::<T>(&refresh_state).await {
+            Ok(_) => refresh_state.single_flight.clear_failure(cache_key),
+            Err(err) => {
+                tracing::warn!("background refresh of {cache_key} failed: {err:#}");
+                refresh_state
+                    .single_flight
+                    .record_failure(cache_key, Utc::now());
+            }
+        }
+
+        drop(guard);
+    });
+}
+
+/// Refreshes the value unless another caller did so while this one waited
+/// for the lock, so concurrent misses for a key share a single `api_fetch`
+async fn refresh_once<T: Cacheable>(state: &AppState) -> anyhow::Result<(T, DateTime<Utc>)> {
+    let _guard = state.single_flight.acquire(T::cache_key()).await;
 
-        // redis uses -1 and -2 as control codes
-        remaining_ttl
-            .filter(|ttl| ttl > &0)
-            .map(|ttl| request_time + Duration::seconds(ttl))
+    refresh_once_locked(state).await
+}
+
+/// `refresh_once` for a caller already holding the key's single-flight lock
+async fn refresh_once_locked<T: Cacheable>(state: &AppState) -> anyhow::Result<(T, DateTime<Utc>)> {
+    if let Some(entry) = cache_read_fresh::<T>(&state.cache).await {
+        return Ok((entry.data, entry.fresh_until));
     }
 
-    async fn cache_read(redis_client: &Option<redis::Client>) -> Option<Self> {
-        let client = redis_client.as_ref()?;
-        let mut conn = client
-            .get_multiplexed_tokio_connection()
+    if !state.config.cache.distributed_lock {
+        return T::refresh(state).await;
+    }
+
+    refresh_with_distributed_lock(state).await
+}
+
+/// Refreshes while holding a lock in the shared cache, so instances sharing
+/// it don't all fetch the same key
+async fn refresh_with_distributed_lock<T: Cacheable>(
+    state: &AppState,
+) -> anyhow::Result<(T, DateTime<Utc>)> {
+    let lock_key = format!("{}_lock", T::cache_key());
+    let token = rand::rng().random::<u64>().to_string();
+    let give_up_at = Utc::now() + state.config.cache.lock_timeout();
+
+    loop {
+        let lock_until = Utc::now() + state.config.cache.lock_timeout();
+
+        match state
+            .cache
+            .set_if_absent(&lock_key, &token, lock_until)
             .await
-            .map_err(Self::cache_log)
-            .ok()?;
-        let cached_data = conn
-            .get::<CacheKey, Option<String>>(Self::cache_key())
+        {
+            Ok(true) => {
+                // another instance may have finished the same fetch and
+                // released the lock since the cache was last checked
+                let refresh_result = match cache_read_fresh::<T>(&state.cache).await {
+                    Some(entry) => Ok((entry.data, entry.fresh_until)),
+                    None => T::refresh(state).await,
+                };
+
+                let unlock_result = state.cache.delete_if_equal(&lock_key, &token).await;
+                let _ = unlock_result.map_err(cache_log);
+
+                return refresh_result;
+            }
+            Ok(false) => {}
+            Err(err) => {
+                cache_log(err);
+                return T::refresh(state).await;
+            }
+        }
+
+        // the other instance may have died without releasing the lock
+        if Utc::now() >= give_up_at {
+            return T::refresh(state).await;
+        }
+
+        tokio::time::sleep(LOCK_POLL_INTERVAL).await;
+
+        if let Some(entry) = cache_read_fresh::<T>(&state.cache).await {
+            return Ok((entry.data, entry.fresh_until));
+        }
+    }
+}
+
+async fn cache_read_fresh<T: Cacheable>(cache: &CacheBackend) -> Option<CacheEntry<T>> {
+    cache_read::<T>(cache)
+        .await
+        .filter(|entry| entry.fresh_until > Utc::now())
+}
+
+async fn cache_read<T: Cacheable>(cache: &CacheBackend) -> Option<CacheEntry<T>> {
+    let cached_data = cache
+        .get(&T::cache_key().to_string())
+        .await
+        .map_err(cache_log)
+        .ok()
+        .flatten()?;
+
+    serde_json::from_str::<CacheEntry<T>>(&cached_data)
+        .map_err(cache_log)
+        .ok()
+}
+
+async fn cache_write<T: serde::Serialize>(
+    cache: &CacheBackend,
+    cache_key: CacheKey,
+    data: &T,
+    retain_until: DateTime<Utc>,
+) -> anyhow::Result<()> {
+    let json_data = serde_json::to_string(data)?;
+
+    cache
+        .set(&cache_key.to_string(), json_data, retain_until)
+        .await
+}
+
+fn cache_log<E>(err: E)
+where
+    E: Into<anyhow::Error>,
+{
+    let cache_warning = format!("cache issue: {}", err.into());
+    tracing::warn!(cache_warning);
+}
+
+#[cfg(test)]
+mod test_super {
+    use std::sync::{
+        atomic::{AtomicU32, Ordering},
+        Arc,
+    };
+
+    use super::*;
+    use crate::api::{
+        cacheable::{backend::MemoryCache, single_flight::SingleFlight},
+        refresh::RefreshLimiter,
+        registry::Registry,
+    };
+
+    fn memory_state() -> AppState {
+        AppState {
+            cache: CacheBackend::Memory(MemoryCache::default()),
+            single_flight: SingleFlight::default(),
+            refresh_limiter: RefreshLimiter::default(),
+            registry: Arc::new(Registry::new(&[])),
+            config: Arc::new(Config::default()),
+        }
+    }
+
+    // Each test uses its own `TestData`, so none of them share a fetch counter
+    const FRESH: usize = 0;
+    const OVERWRITE: usize = 1;
+    const STALE: usize = 2;
+    const SLOW: usize = 3;
+    const SLOW_STALE: usize = 4;
+    const FAILING: usize = 5;
+    const INCREMENTAL: usize = 6;
+    const LOCKED: usize = 7;
+
+    static FETCHES: [AtomicU32; 8] = [const { AtomicU32::new(0) }; 8];
+
+    fn fetch_count(test: usize) -> u32 {
+        FETCHES[test].load(Ordering::SeqCst)
+    }
+
+    /// Counts its fetches, behaving as the test it's for needs: expiring
+    /// straight away, taking a while to fetch, failing after the first fetch,
+    /// or syncing incrementally
+    #[derive(serde::Serialize, serde::Deserialize)]
+    struct TestData<const TEST: usize> {
+        fetch_number: u32,
+    }
+
+    impl<const TEST: usize> Cacheable for TestData<TEST> {
+        const INCREMENTAL: bool = TEST == INCREMENTAL;
+
+        fn cache_key() -> CacheKey {
+            CacheKey::Anki
+        }
+
+        fn expires_at(&self, config: &Config) -> DateTime<Utc> {
+            match TEST {
+                STALE | SLOW_STALE | FAILING => Utc::now() - Duration::minutes(1),
+                _ => Utc::now() + config.cache.ttl(),
+            }
+        }
+
+        async fn api_fetch(_config: &Config) -> anyhow::Result<Self> {
+            let fetch_number = FETCHES[TEST].fetch_add(1, Ordering::SeqCst) + 1;
+
+            if matches!(TEST, SLOW | SLOW_STALE) {
+                tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+            }
+
+            if TEST == FAILING && fetch_number > 1 {
+                anyhow::bail!("upstream is down");
+            }
+
+            Ok(Self { fetch_number })
+        }
+
+        async fn api_update(state: &AppState, previous: Option<Self>) -> anyhow::Result<Self> {
+            match previous {
+                Some(previous) => Ok(Self {
+                    fetch_number: previous.fetch_number + 1,
+                }),
+                None => Self::api_fetch(&state.config).await,
+            }
+        }
+    }
+
+    /// Lets spawned background refreshes run
+    async fn wait_for_fetches(test: usize, count: u32) {
+        for _ in 0..100 {
+            if fetch_count(test) >= count {
+                break;
+            }
+            tokio::task::yield_now().await;
+        }
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_misses_share_one_fetch() {
+        let mut config = Config::default();
+        config.cache.distributed_lock = true;
+        let state = AppState {
+            config: Arc::new(config),
+            ..memory_state()
+        };
+
+        // a separate lock map acts as another instance sharing the cache
+        let other_instance = AppState {
+            single_flight: SingleFlight::default(),
+            ..state.clone()
+        };
+
+        let (first, second, third) = tokio::join!(
+            TestData::<SLOW>::get(&state),
+            TestData::<SLOW>::get(&state),
+            TestData::<SLOW>::get(&other_instance),
+        );
+
+        assert_eq!(fetch_count(SLOW), 1);
+        assert_eq!(first.unwrap().data.fetch_number, 1);
+        assert_eq!(second.unwrap().data.fetch_number, 1);
+        assert_eq!(third.unwrap().data.fetch_number, 1);
+    }
+
+    #[tokio::test]
+    async fn test_lock_holder_rechecks_cache_before_fetching() {
+        let state = memory_state();
+
+        // another instance refreshed after this one's first cache check
+        TestData::<LOCKED>::refresh(&state).await.unwrap();
+
+        let (data, _) = refresh_with_distributed_lock::<TestData<LOCKED>>(&state)
             .await
-            .map_err(Self::cache_log)
-            .ok()
-            .flatten()?;
+            .unwrap();
+
+        assert_eq!(data.fetch_number, 1);
+        assert_eq!(fetch_count(LOCKED), 1);
+    }
+
+    #[test]
+    fn test_expiry_between_uses_next_change_within_ttl() {
+        let now = Utc.with_ymd_and_hms(2024, 6, 21, 10, 0, 0).unwrap();
+        let ttl = Duration::hours(1);
 
-        serde_json::from_str::<Self>(&cached_data)
-            .map_err(Self::cache_log)
-            .ok()
+        assert_eq!(
+            expiry_between(now, Some(now + Duration::minutes(20)), ttl),
+            now + Duration::minutes(20) + Duration::seconds(30)
+        );
+        assert_eq!(
+            expiry_between(now, Some(now + Duration::hours(3)), ttl),
+            now + ttl
+        );
+        assert_eq!(
+            expiry_between(now, Some(now - Duration::minutes(5)), ttl),
+            now + ttl
+        );
+        assert_eq!(expiry_between(now, None, ttl), now + ttl);
     }
 
-    async fn cache_write(
-        redis_client: &Option<redis::Client>,
-        data: &Mutex<Self>,
-    ) -> anyhow::Result<()> {
-        let client = redis_client
-            .as_ref()
-            .ok_or(anyhow!("No Redis Client set"))?;
-        let mut conn = client.get_multiplexed_tokio_connection().await?;
-        // not sure why it throws a compile error here and not in the value passed to serde_json
-        // let unwrapped_data = *data.lock().await;
-        let json_data = serde_json::to_string(&*data.lock().await)?;
+    #[test]
+    fn test_next_jst_midnight() {
+        // 23:30 JST on the 21st
+        let now = Utc.with_ymd_and_hms(2024, 6, 21, 14, 30, 0).unwrap();
+        assert_eq!(
+            next_jst_midnight(now),
+            Utc.with_ymd_and_hms(2024, 6, 21, 15, 0, 0).unwrap()
+        );
 
-        let unix_timestamp_expiry = u64::try_from(Self::expires_at().timestamp())?;
-        let options =
-            SetOptions::default().with_expiration(redis::SetExpiry::EXAT(unix_timestamp_expiry));
+        // 00:30 JST on the 22nd
+        let now = Utc.with_ymd_and_hms(2024, 6, 21, 15, 30, 0).unwrap();
+        assert_eq!(
+            next_jst_midnight(now),
+            Utc.with_ymd_and_hms(2024, 6, 22, 15, 0, 0).unwrap()
+        );
+    }
 
-        let set_response = conn
-            .set_options(Self::cache_key(), json_data, options)
-            .await;
+    #[tokio::test]
+    async fn test_get_reads_from_cache_while_fresh() {
+        let state = memory_state();
 
-        Ok(set_response?)
+        let first = TestData::<FRESH>::get(&state).await.unwrap();
+        let second = TestData::<FRESH>::get(&state).await.unwrap();
+
+        assert_eq!(second.data.fetch_number, first.data.fetch_number);
+        assert_eq!(second.expires_at, first.expires_at);
+        assert!(second.stale_since.is_none());
     }
 
-    fn cache_log<E>(err: E)
-    where
-        E: Into<anyhow::Error>,
-    {
-        let redis_warning = format!("redis issue: {}", err.into());
-        tracing::warn!(redis_warning);
+    #[tokio::test]
+    async fn test_get_serves_stale_value_and_refreshes() {
+        let state = memory_state();
+
+        let first = TestData::<STALE>::get(&state).await.unwrap();
+        let second = TestData::<STALE>::get(&state).await.unwrap();
+
+        assert_eq!(first.data.fetch_number, 1);
+        assert_eq!(second.data.fetch_number, 1);
+        assert!(second.stale_since.is_some());
+
+        wait_for_fetches(STALE, 2).await;
+
+        assert_eq!(fetch_count(STALE), 2);
+    }
+
+    #[tokio::test]
+    async fn test_stale_reads_skip_refresh_already_running() {
+        let state = memory_state();
+
+        TestData::<SLOW_STALE>::get(&state).await.unwrap();
+
+        for _ in 0..3 {
+            let stale = TestData::<SLOW_STALE>::get(&state).await.unwrap();
+            assert!(stale.stale_since.is_some());
+        }
+
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+
+        assert_eq!(fetch_count(SLOW_STALE), 2);
+    }
+
+    #[tokio::test]
+    async fn test_stale_reads_back_off_after_failed_refresh() {
+        let state = memory_state();
+
+        TestData::<FAILING>::get(&state).await.unwrap();
+        TestData::<FAILING>::get(&state).await.unwrap();
+        wait_for_fetches(FAILING, 2).await;
+
+        // wait for the failure to be recorded and the lock released
+        for _ in 0..100 {
+            if state.single_flight.try_acquire(CacheKey::Anki).is_some() {
+                break;
+            }
+            tokio::task::yield_now().await;
+        }
+
+        for _ in 0..3 {
+            let stale = TestData::<FAILING>::get(&state).await.unwrap();
+            assert_eq!(stale.data.fetch_number, 1);
+        }
+        tokio::task::yield_now().await;
+
+        assert_eq!(fetch_count(FAILING), 2);
+    }
+
+    #[tokio::test]
+    async fn test_refresh_passes_cached_value_to_incremental_update() {
+        let state = memory_state();
+
+        TestData::<INCREMENTAL>::refresh(&state).await.unwrap();
+        let (second, _) = TestData::<INCREMENTAL>::refresh(&state).await.unwrap();
+
+        // the second sync builds on the first rather than fetching again
+        assert_eq!(second.fetch_number, 2);
+        assert_eq!(fetch_count(INCREMENTAL), 1);
+    }
+
+    #[tokio::test]
+    async fn test_refresh_overwrites_fresh_value() {
+        let state = AppState {
+            cache: CacheBackend::Noop,
+            ..memory_state()
+        };
+
+        let (first, _) = TestData::<OVERWRITE>::refresh(&state).await.unwrap();
+        let (second, _) = TestData::<OVERWRITE>::refresh(&state).await.unwrap();
+
+        assert_eq!((first.fetch_number, second.fetch_number), (1, 2));
     }
 }
@@ -0,0 +1,321 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+use anyhow::anyhow;
+use chrono::{DateTime, Utc};
+use redis::{AsyncCommands, ExistenceCheck, Script, SetOptions};
+
+use crate::config::{CacheBackendKind, CacheConfig};
+
+/// Where `Cacheable` values are stored. Redis is used in production, the
+/// in-memory store for local development and single machine deploys
+#[derive(Clone)]
+pub enum CacheBackend {
+    Redis(redis::Client),
+    Memory(MemoryCache),
+    Noop,
+}
+
+impl CacheBackend {
+    pub fn from_config(config: &CacheConfig) -> anyhow::Result<Self> {
+        match config.backend() {
+            CacheBackendKind::Redis => {
+                let redis_url = config
+                    .redis_url
+                    .as_deref()
+                    .ok_or(anyhow!("cache.redis_url is required for the redis backend"))?;
+
+                Ok(Self::Redis(redis::Client::open(redis_url)?))
+            }
+            CacheBackendKind::Memory => Ok(Self::Memory(MemoryCache::default())),
+            CacheBackendKind::None => Ok(Self::Noop),
+        }
+    }
+
+    pub async fn get(&self, key: &str) -> anyhow::Result<Option<String>> {
+        match self {
+            Self::Redis(client) => {
+                let mut conn = client.get_multiplexed_tokio_connection().await?;
+
+                Ok(conn.get(key).await?)
+            }
+            Self::Memory(memory_cache) => Ok(memory_cache.get(key, Utc::now())),
+            Self::Noop => Ok(None),
+        }
+    }
+
+    pub async fn set(
+        &self,
+        key: &str,
+        value: String,
+        expires_at: DateTime<Utc>,
+    ) -> anyhow::Result<()> {
+        match self {
+            Self::Redis(client) => {
+                let mut conn = client.get_multiplexed_tokio_connection().await?;
+
+                let unix_timestamp_expiry = u64::try_from(expires_at.timestamp())?;
+                let options = SetOptions::default()
+                    .with_expiration(redis::SetExpiry::EXAT(unix_timestamp_expiry));
+
+                Ok(conn.set_options(key, value, options).await?)
+            }
+            Self::Memory(memory_cache) => {
+                memory_cache.set(key, value, expires_at, Utc::now());
+
+                Ok(())
+            }
+            Self::Noop => Ok(()),
+        }
+    }
+
+    /// Sets the key only if it doesn't already exist, returning whether it
+    /// was set. Used as a lock shared between instances
+    pub async fn set_if_absent(
+        &self,
+        key: &str,
+        value: &str,
+        expires_at: DateTime<Utc>,
+    ) -> anyhow::Result<bool> {
+        match self {
+            Self::Redis(client) => {
+                let mut conn = client.get_multiplexed_tokio_connection().await?;
+
+                let unix_timestamp_expiry = u64::try_from(expires_at.timestamp())?;
+                let options = SetOptions::default()
+                    .conditional_set(ExistenceCheck::NX)
+                    .with_expiration(redis::SetExpiry::EXAT(unix_timestamp_expiry));
+
+                let set_response: Option<String> = conn.set_options(key, value, options).await?;
+
+                Ok(set_response.is_some())
+            }
+            Self::Memory(memory_cache) => {
+                Ok(memory_cache.set_if_absent(key, value, expires_at, Utc::now()))
+            }
+            Self::Noop => Ok(true),
+        }
+    }
+
+    /// Deletes the key if it still holds `value`, so a lock that has expired
+    /// and been taken by someone else isn't released
+    pub async fn delete_if_equal(&self, key: &str, value: &str) -> anyhow::Result<()> {
+        match self {
+            Self::Redis(client) => {
+                let mut conn = client.get_multiplexed_tokio_connection().await?;
+
+                let script = Script::new(
+                    r#"if redis.call("get", KEYS[1]) == ARGV[1] then
+                        return redis.call("del", KEYS[1])
+                    else
+                        return 0
+                    end"#,
+                );
+
+                let _: i32 = script.key(key).arg(value).invoke_async(&mut conn).await?;
+
+                Ok(())
+            }
+            Self::Memory(memory_cache) => {
+                memory_cache.delete_if_equal(key, value);
+
+                Ok(())
+            }
+            Self::Noop => Ok(()),
+        }
+    }
+
+    pub async fn delete(&self, key: &str) -> anyhow::Result<()> {
+        match self {
+            Self::Redis(client) => {
+                let mut conn = client.get_multiplexed_tokio_connection().await?;
+
+                Ok(conn.del(key).await?)
+            }
+            Self::Memory(memory_cache) => {
+                memory_cache.delete(key);
+
+                Ok(())
+            }
+            Self::Noop => Ok(()),
+        }
+    }
+}
+
+struct MemoryEntry {
+    value: String,
+    expires_at: DateTime<Utc>,
+}
+
+#[derive(Clone, Default)]
+pub struct MemoryCache {
+    entries: Arc<Mutex<HashMap<String, MemoryEntry>>>,
+}
+
+impl MemoryCache {
+    fn get(&self, key: &str, now: DateTime<Utc>) -> Option<String> {
+        let entries = self.entries.lock().unwrap();
+
+        entries
+            .get(key)
+            .filter(|entry| entry.expires_at > now)
+            .map(|entry| entry.value.clone())
+    }
+
+    fn set(&self, key: &str, value: String, expires_at: DateTime<Utc>, now: DateTime<Utc>) {
+        let mut entries = self.entries.lock().unwrap();
+
+        // expired entries are only dropped on write, there are few enough
+        // keys that a full sweep is cheap
+        entries.retain(|_, entry| entry.expires_at > now);
+        entries.insert(key.to_string(), MemoryEntry { value, expires_at });
+    }
+
+    fn set_if_absent(
+        &self,
+        key: &str,
+        value: &str,
+        expires_at: DateTime<Utc>,
+        now: DateTime<Utc>,
+    ) -> bool {
+        // checked and inserted under one lock, so only one caller can win
+        let mut entries = self.entries.lock().unwrap();
+
+        if entries.get(key).is_some_and(|entry| entry.expires_at > now) {
+            return false;
+        }
+
+        entries.insert(
+            key.to_string(),
+            MemoryEntry {
+                value: value.to_string(),
+                expires_at,
+            },
+        );
+
+        true
+    }
+
+    fn delete(&self, key: &str) {
+        self.entries.lock().unwrap().remove(key);
+    }
+
+    fn delete_if_equal(&self, key: &str, value: &str) {
+        let mut entries = self.entries.lock().unwrap();
+
+        if entries.get(key).is_some_and(|entry| entry.value == value) {
+            entries.remove(key);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test_super {
+    use chrono::{Duration, TimeZone};
+
+    use super::*;
+
+    #[test]
+    fn test_memory_cache_returns_value_before_expiry() {
+        let memory_cache = MemoryCache::default();
+        let now = Utc.with_ymd_and_hms(2024, 6, 21, 10, 0, 0).unwrap();
+
+        memory_cache.set("key", "value".to_string(), now + Duration::hours(1), now);
+
+        assert_eq!(memory_cache.get("key", now), Some("value".to_string()));
+        assert_eq!(memory_cache.get("key", now + Duration::hours(1)), None);
+    }
+
+    #[test]
+    fn test_memory_cache_drops_expired_entries_on_write() {
+        let memory_cache = MemoryCache::default();
+        let now = Utc.with_ymd_and_hms(2024, 6, 21, 10, 0, 0).unwrap();
+
+        memory_cache.set("old", "value".to_string(), now + Duration::minutes(1), now);
+        memory_cache.set(
+            "new",
+            "value".to_string(),
+            now + Duration::hours(1),
+            now + Duration::minutes(2),
+        );
+
+        assert_eq!(memory_cache.entries.lock().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_memory_cache_delete() {
+        let memory_cache = MemoryCache::default();
+        let now = Utc::now();
+
+        memory_cache.set("key", "value".to_string(), now + Duration::hours(1), now);
+        memory_cache.delete("key");
+
+        assert_eq!(memory_cache.get("key", now), None);
+    }
+
+    #[test]
+    fn test_memory_cache_set_if_absent() {
+        let memory_cache = MemoryCache::default();
+        let now = Utc.with_ymd_and_hms(2024, 6, 21, 10, 0, 0).unwrap();
+        let expires_at = now + Duration::seconds(30);
+
+        assert!(memory_cache.set_if_absent("lock", "a", expires_at, now));
+        assert!(!memory_cache.set_if_absent("lock", "b", expires_at, now));
+        assert!(memory_cache.set_if_absent("lock", "b", expires_at, expires_at));
+    }
+
+    #[test]
+    fn test_memory_cache_set_if_absent_has_one_winner() {
+        let memory_cache = MemoryCache::default();
+        let now = Utc::now();
+        let expires_at = now + Duration::seconds(30);
+
+        let winners = std::thread::scope(|scope| {
+            let handles: Vec<_> = (0..8)
+                .map(|i| {
+                    let memory_cache = &memory_cache;
+                    scope.spawn(move || {
+                        memory_cache.set_if_absent("lock", &i.to_string(), expires_at, now)
+                    })
+                })
+                .collect();
+
+            handles
+                .into_iter()
+                .map(|handle| handle.join().unwrap())
+                .filter(|won| *won)
+                .count()
+        });
+
+        assert_eq!(winners, 1);
+    }
+
+    #[test]
+    fn test_memory_cache_delete_if_equal() {
+        let memory_cache = MemoryCache::default();
+        let now = Utc::now();
+        let expires_at = now + Duration::seconds(30);
+
+        memory_cache.set("lock", "a".to_string(), expires_at, now);
+
+        memory_cache.delete_if_equal("lock", "b");
+        assert_eq!(memory_cache.get("lock", now), Some("a".to_string()));
+
+        memory_cache.delete_if_equal("lock", "a");
+        assert_eq!(memory_cache.get("lock", now), None);
+    }
+
+    #[tokio::test]
+    async fn test_noop_backend_never_stores() {
+        let backend = CacheBackend::Noop;
+
+        backend
+            .set("key", "value".to_string(), Utc::now() + Duration::hours(1))
+            .await
+            .unwrap();
+
+        assert_eq!(backend.get("key").await.unwrap(), None);
+    }
+}
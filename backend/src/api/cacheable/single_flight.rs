@@ -0,0 +1,62 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+use chrono::{DateTime, Duration, Utc};
+use tokio::sync::OwnedMutexGuard;
+
+use super::CacheKey;
+
+/// Per `CacheKey` locks so only one task in the process fetches a given key
+/// at a time. Waiting tasks re-check the cache once they get the lock, and
+/// find the value the first task wrote
+#[derive(Clone, Default)]
+pub struct SingleFlight {
+    locks: Arc<Mutex<HashMap<CacheKey, Arc<tokio::sync::Mutex<()>>>>>,
+    /// When each key's last background refresh failed, cleared on success
+    failed_at: Arc<Mutex<HashMap<CacheKey, DateTime<Utc>>>>,
+}
+
+impl SingleFlight {
+    pub async fn acquire(&self, cache_key: CacheKey) -> OwnedMutexGuard<()> {
+        self.lock_for(cache_key).lock_owned().await
+    }
+
+    /// The lock for `cache_key`, unless a fetch of it is already running
+    pub fn try_acquire(&self, cache_key: CacheKey) -> Option<OwnedMutexGuard<()>> {
+        self.lock_for(cache_key).try_lock_owned().ok()
+    }
+
+    fn lock_for(&self, cache_key: CacheKey) -> Arc<tokio::sync::Mutex<()>> {
+        // the map only grows to the number of `CacheKey` variants
+        self.locks
+            .lock()
+            .unwrap()
+            .entry(cache_key)
+            .or_default()
+            .clone()
+    }
+
+    pub fn record_failure(&self, cache_key: CacheKey, now: DateTime<Utc>) {
+        self.failed_at.lock().unwrap().insert(cache_key, now);
+    }
+
+    pub fn clear_failure(&self, cache_key: CacheKey) {
+        self.failed_at.lock().unwrap().remove(&cache_key);
+    }
+
+    /// Whether a refresh of `cache_key` failed within the last `cooldown`
+    pub fn failed_within(
+        &self,
+        cache_key: CacheKey,
+        cooldown: Duration,
+        now: DateTime<Utc>,
+    ) -> bool {
+        self.failed_at
+            .lock()
+            .unwrap()
+            .get(&cache_key)
+            .is_some_and(|failed_at| now - *failed_at < cooldown)
+    }
+}
@@ -0,0 +1,156 @@
+use chrono::{DateTime, Duration, Utc};
+
+use crate::templates::utils::format_hour;
+
+/// Width of each hour in the chart's viewBox
+const HOUR_WIDTH: u32 = 10;
+const CHART_HEIGHT: u32 = 50;
+
+/// Reviews unlocking in an hour, and the total that will be due by then
+#[derive(serde::Serialize, Clone, Debug, PartialEq)]
+pub struct ForecastHour {
+    pub available_at: DateTime<Utc>,
+    pub review_count: u32,
+    pub cumulative_count: u32,
+}
+
+/// Hour by hour forecast for the 24 hours after `start`, on top of the
+/// reviews that are already due
+pub fn hourly_forecast(
+    start: DateTime<Utc>,
+    current_count: u32,
+    hours: impl IntoIterator<Item = (DateTime<Utc>, u32)>,
+) -> Vec<ForecastHour> {
+    let end = start + Duration::hours(24);
+    let mut cumulative_count = current_count;
+
+    hours
+        .into_iter()
+        .filter(|(available_at, _)| *available_at > start && *available_at <= end)
+        .map(|(available_at, review_count)| {
+            cumulative_count += review_count;
+
+            ForecastHour {
+                available_at,
+                review_count,
+                cumulative_count,
+            }
+        })
+        .collect()
+}
+
+pub struct ForecastBar {
+    pub x: u32,
+    pub y: u32,
+    pub height: u32,
+    pub label: String,
+}
+
+/// A forecast laid out as an svg bar chart of each hour, with a line for
+/// the cumulative count. Each is scaled to its own maximum
+pub struct ForecastChart {
+    pub bars: Vec<ForecastBar>,
+    pub line_points: String,
+    pub total: u32,
+}
+
+impl ForecastChart {
+    pub fn new(forecast: &[ForecastHour]) -> Self {
+        let max_review_count = forecast.iter().map(|h| h.review_count).max().unwrap_or(0);
+        let max_cumulative_count = forecast.last().map(|h| h.cumulative_count).unwrap_or(0);
+
+        let bars = forecast
+            .iter()
+            .enumerate()
+            .map(|(index, hour)| {
+                let height = scale(hour.review_count, max_review_count);
+
+                ForecastBar {
+                    x: index as u32 * HOUR_WIDTH,
+                    y: CHART_HEIGHT - height,
+                    height,
+                    label: format!(
+                        "{}: {} reviews ({} total)",
+                        format_hour(&hour.available_at),
+                        hour.review_count,
+                        hour.cumulative_count
+                    ),
+                }
+            })
+            .collect();
+
+        let line_points = forecast
+            .iter()
+            .enumerate()
+            .map(|(index, hour)| {
+                let x = index as u32 * HOUR_WIDTH + HOUR_WIDTH / 2;
+                let y = CHART_HEIGHT - scale(hour.cumulative_count, max_cumulative_count);
+
+                format!("{x},{y}")
+            })
+            .collect::<Vec<_>>()
+            .join(" ");
+
+        Self {
+            bars,
+            line_points,
+            total: forecast.iter().map(|h| h.review_count).sum(),
+        }
+    }
+}
+
+fn scale(count: u32, max_count: u32) -> u32 {
+    if max_count == 0 {
+        return 0;
+    }
+
+    count * CHART_HEIGHT / max_count
+}
+
+#[cfg(test)]
+mod test_super {
+    use chrono::TimeZone;
+
+    use super::*;
+
+    #[test]
+    fn test_hourly_forecast_is_cumulative_within_24_hours() {
+        let start = Utc.with_ymd_and_hms(2023, 6, 23, 13, 0, 0).unwrap();
+        let hours = [
+            (start, 10),
+            (start + Duration::hours(1), 0),
+            (start + Duration::hours(2), 5),
+            (start + Duration::hours(24), 2),
+            (start + Duration::hours(25), 8),
+        ];
+
+        let forecast = hourly_forecast(start, 10, hours);
+
+        let counts: Vec<(u32, u32)> = forecast
+            .iter()
+            .map(|h| (h.review_count, h.cumulative_count))
+            .collect();
+
+        assert_eq!(counts, vec![(0, 10), (5, 15), (2, 17)]);
+    }
+
+    #[test]
+    fn test_forecast_chart_scales_bars_and_line() {
+        let start = Utc.with_ymd_and_hms(2023, 6, 23, 13, 0, 0).unwrap();
+        let forecast = hourly_forecast(
+            start,
+            0,
+            [
+                (start + Duration::hours(1), 4),
+                (start + Duration::hours(2), 2),
+            ],
+        );
+
+        let chart = ForecastChart::new(&forecast);
+
+        let heights: Vec<u32> = chart.bars.iter().map(|b| b.height).collect();
+        assert_eq!(heights, vec![50, 25]);
+        assert_eq!(chart.line_points, "5,17 15,0");
+        assert_eq!(chart.total, 6);
+    }
+}
@@ -0,0 +1,286 @@
+use std::collections::BTreeMap;
+
+use chrono::{Datelike, Duration, NaiveDate};
+
+/// Weeks shown in the heatmap, ending with the current week
+pub const HEATMAP_WEEKS: u32 = 26;
+/// Width and height of each day in the heatmap's viewBox
+const CELL_SIZE: u32 = 10;
+pub const HEATMAP_LEVELS: u32 = 4;
+
+/// Reviews done over the trailing days up to today, and the average per day
+#[derive(serde::Serialize, Debug, PartialEq)]
+pub struct PeriodTotals {
+    pub total: u32,
+    pub daily_average: f64,
+}
+
+/// Day by day review counts, with the streaks and totals built from them.
+/// Days are JST days, ending with `today`
+#[derive(serde::Serialize, Debug, PartialEq)]
+pub struct StudyHistory {
+    pub today: NaiveDate,
+    pub daily_counts: BTreeMap<NaiveDate, u32>,
+    pub current_streak: u32,
+    pub longest_streak: u32,
+    pub last_7_days: PeriodTotals,
+    pub last_30_days: PeriodTotals,
+}
+
+impl StudyHistory {
+    pub fn new(daily_counts: BTreeMap<NaiveDate, u32>, today: NaiveDate) -> Self {
+        Self {
+            current_streak: current_streak(today, |date| count_on(&daily_counts, date) > 0),
+            longest_streak: longest_streak(&daily_counts),
+            last_7_days: period_totals(&daily_counts, today, 7),
+            last_30_days: period_totals(&daily_counts, today, 30),
+            today,
+            daily_counts,
+        }
+    }
+
+    pub fn heatmap(&self) -> Heatmap {
+        Heatmap::new(&self.daily_counts, self.today)
+    }
+}
+
+fn count_on(daily_counts: &BTreeMap<NaiveDate, u32>, date: NaiveDate) -> u32 {
+    daily_counts.get(&date).copied().unwrap_or(0)
+}
+
+/// Days in a row that were `studied`, up to today. Today not being studied
+/// yet doesn't break the streak, as there's still time left to do it
+pub fn current_streak(today: NaiveDate, studied: impl Fn(NaiveDate) -> bool) -> u32 {
+    let mut date = today;
+
+    if !studied(date) {
+        date -= Duration::days(1);
+    }
+
+    let mut streak = 0;
+
+    while studied(date) {
+        streak += 1;
+        date -= Duration::days(1);
+    }
+
+    streak
+}
+
+fn longest_streak(daily_counts: &BTreeMap<NaiveDate, u32>) -> u32 {
+    let mut longest = 0;
+    let mut streak = 0;
+    let mut previous_date: Option<NaiveDate> = None;
+
+    for (&date, &count) in daily_counts {
+        if count == 0 {
+            streak = 0;
+        } else if previous_date.is_some_and(|previous| previous + Duration::days(1) == date) {
+            streak += 1;
+        } else {
+            streak = 1;
+        }
+
+        longest = longest.max(streak);
+        previous_date = Some(date).filter(|_| count > 0);
+    }
+
+    longest
+}
+
+fn period_totals(
+    daily_counts: &BTreeMap<NaiveDate, u32>,
+    today: NaiveDate,
+    days: u32,
+) -> PeriodTotals {
+    let start = today - Duration::days(i64::from(days) - 1);
+    let total: u32 = daily_counts
+        .range(start..=today)
+        .map(|(_, count)| count)
+        .sum();
+    let daily_average = f64::from(total) / f64::from(days);
+
+    PeriodTotals {
+        total,
+        daily_average: (daily_average * 10.0).round() / 10.0,
+    }
+}
+
+pub struct HeatmapCell {
+    pub x: u32,
+    pub y: u32,
+    /// 0 for no reviews, up to `HEATMAP_LEVELS` for the busiest days
+    pub level: u32,
+    pub label: String,
+}
+
+/// A calendar heatmap laid out as an svg, one column per week starting on
+/// Monday. Levels are scaled to the busiest day shown
+pub struct Heatmap {
+    pub cells: Vec<HeatmapCell>,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Heatmap {
+    pub fn new(daily_counts: &BTreeMap<NaiveDate, u32>, today: NaiveDate) -> Self {
+        let max_count = heatmap_days(today)
+            .map(|date| count_on(daily_counts, date))
+            .max()
+            .unwrap_or(0);
+
+        Self::from_levels(today, |date| {
+            let count = count_on(daily_counts, date);
+
+            (
+                heat_level(count, max_count),
+                format!("{}: {count} reviews", date.format("%Y-%m-%d")),
+            )
+        })
+    }
+
+    /// Heatmap for services that give their own level for each day, from 0
+    /// up to `HEATMAP_LEVELS`
+    pub fn from_levels(
+        today: NaiveDate,
+        level_and_label: impl Fn(NaiveDate) -> (u32, String),
+    ) -> Self {
+        let start = heatmap_start(today);
+
+        let cells = heatmap_days(today)
+            .map(|date| {
+                let days_since_start = (date - start).num_days() as u32;
+                let (level, label) = level_and_label(date);
+
+                HeatmapCell {
+                    x: days_since_start / 7 * CELL_SIZE,
+                    y: date.weekday().num_days_from_monday() * CELL_SIZE,
+                    level,
+                    label,
+                }
+            })
+            .collect();
+
+        Self {
+            cells,
+            width: HEATMAP_WEEKS * CELL_SIZE,
+            height: 7 * CELL_SIZE,
+        }
+    }
+}
+
+/// Start of the week `date` is in. Weeks start on Monday
+pub fn week_start(date: NaiveDate) -> NaiveDate {
+    date - Duration::days(i64::from(date.weekday().num_days_from_monday()))
+}
+
+fn heatmap_start(today: NaiveDate) -> NaiveDate {
+    week_start(today) - Duration::weeks(i64::from(HEATMAP_WEEKS) - 1)
+}
+
+fn heatmap_days(today: NaiveDate) -> impl Iterator<Item = NaiveDate> {
+    heatmap_start(today)
+        .iter_days()
+        .take_while(move |date| *date <= today)
+}
+
+fn heat_level(count: u32, max_count: u32) -> u32 {
+    if count == 0 || max_count == 0 {
+        return 0;
+    }
+
+    (count * HEATMAP_LEVELS).div_ceil(max_count)
+}
+
+#[cfg(test)]
+mod test_super {
+    use super::*;
+
+    fn date(value: &str) -> NaiveDate {
+        NaiveDate::parse_from_str(value, "%Y-%m-%d").unwrap()
+    }
+
+    fn counts(days: &[(&str, u32)]) -> BTreeMap<NaiveDate, u32> {
+        days.iter()
+            .map(|(day, count)| (date(day), *count))
+            .collect()
+    }
+
+    #[test]
+    fn test_current_streak_allows_today_to_be_empty() {
+        let daily_counts = counts(&[
+            ("2023-10-01", 5),
+            ("2023-10-02", 0),
+            ("2023-10-03", 3),
+            ("2023-10-04", 8),
+        ]);
+
+        let studied = |date| count_on(&daily_counts, date) > 0;
+
+        assert_eq!(current_streak(date("2023-10-04"), studied), 2);
+        assert_eq!(current_streak(date("2023-10-05"), studied), 2);
+        assert_eq!(current_streak(date("2023-10-06"), studied), 0);
+    }
+
+    #[test]
+    fn test_longest_streak_skips_gaps_and_empty_days() {
+        let daily_counts = counts(&[
+            ("2023-10-01", 5),
+            ("2023-10-02", 6),
+            ("2023-10-03", 7),
+            ("2023-10-04", 0),
+            ("2023-10-05", 1),
+            ("2023-10-07", 1),
+            ("2023-10-08", 1),
+        ]);
+
+        assert_eq!(longest_streak(&daily_counts), 3);
+    }
+
+    #[test]
+    fn test_period_totals_include_today() {
+        let daily_counts = counts(&[
+            ("2023-09-01", 100),
+            ("2023-09-27", 10),
+            ("2023-10-01", 4),
+            ("2023-10-04", 10),
+        ]);
+
+        let history = StudyHistory::new(daily_counts, date("2023-10-04"));
+
+        assert_eq!(
+            history.last_7_days,
+            PeriodTotals {
+                total: 14,
+                daily_average: 2.0
+            }
+        );
+        assert_eq!(
+            history.last_30_days,
+            PeriodTotals {
+                total: 24,
+                daily_average: 0.8
+            }
+        );
+    }
+
+    #[test]
+    fn test_heatmap_lays_out_weeks_from_monday() {
+        // a Wednesday
+        let today = date("2023-10-04");
+        let daily_counts = counts(&[("2023-10-02", 2), ("2023-10-04", 8)]);
+
+        let heatmap = Heatmap::new(&daily_counts, today);
+
+        assert_eq!(heatmap.cells.len() as u32, (HEATMAP_WEEKS - 1) * 7 + 3);
+
+        let monday = &heatmap.cells[heatmap.cells.len() - 3];
+        assert_eq!((monday.x, monday.y), ((HEATMAP_WEEKS - 1) * CELL_SIZE, 0));
+        assert_eq!(monday.level, 1);
+
+        let wednesday = heatmap.cells.last().unwrap();
+        assert_eq!(wednesday.y, 2 * CELL_SIZE);
+        assert_eq!(wednesday.level, HEATMAP_LEVELS);
+        assert_eq!(wednesday.label, "2023-10-04: 8 reviews");
+    }
+}
@@ -0,0 +1,165 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+use axum::{
+    extract::State,
+    http::{header, HeaderMap, HeaderValue, StatusCode},
+    response::{Html, IntoResponse, Response},
+};
+use chrono::{DateTime, Duration, Utc};
+
+use crate::{
+    api::{
+        cacheable::CacheSource,
+        internal_error,
+        study_service::{html_handler, StudyService},
+    },
+    state::AppState,
+};
+
+/// Key the refresh-all endpoint is rate limited under
+const ALL_SERVICES: &str = "all";
+
+/// Last manual refresh of each service, so the refresh endpoints can't be
+/// used to hammer the upstream APIs
+#[derive(Clone, Default)]
+pub struct RefreshLimiter {
+    last_refreshed: Arc<Mutex<HashMap<&'static str, DateTime<Utc>>>>,
+}
+
+impl RefreshLimiter {
+    /// Records a refresh of `key`, unless the last one was within `cooldown`.
+    /// Returns how long is left of the cooldown when refusing
+    fn try_refresh(
+        &self,
+        key: &'static str,
+        cooldown: Duration,
+        now: DateTime<Utc>,
+    ) -> Result<(), Duration> {
+        let mut last_refreshed = self.last_refreshed.lock().unwrap();
+
+        if let Some(refreshed_at) = last_refreshed.get(key) {
+            let remaining = *refreshed_at + cooldown - now;
+
+            if remaining > Duration::zero() {
+                return Err(remaining);
+            }
+        }
+
+        last_refreshed.insert(key, now);
+
+        Ok(())
+    }
+}
+
+/// Refetches the service's data, ignoring the cache, and renders the fragment
+pub async fn refresh_handler<S: StudyService>(State(state): State<AppState>) -> Response {
+    if let Err(remaining) = check_rate_limit(&state, S::ID) {
+        return too_many_requests(remaining);
+    }
+
+    if let Err(err) = refresh_sources(&state, S::sources()).await {
+        return internal_error(err).into_response();
+    }
+
+    html_handler::<S>(State(state)).await.into_response()
+}
+
+/// Refetches every enabled service, then has htmx reload the dashboard
+pub async fn refresh_all_handler(State(state): State<AppState>) -> Response {
+    if let Err(remaining) = check_rate_limit(&state, ALL_SERVICES) {
+        return too_many_requests(remaining);
+    }
+
+    let sources = state
+        .registry
+        .services()
+        .iter()
+        .flat_map(|service| (service.sources)())
+        .collect();
+
+    // reload regardless, the cards of services that failed show their stale data
+    if let Err(err) = refresh_sources(&state, sources).await {
+        tracing::warn!("refreshing all services failed: {err:#}");
+    }
+
+    let mut headers = HeaderMap::new();
+    headers.insert("HX-Refresh", HeaderValue::from_static("true"));
+
+    (StatusCode::NO_CONTENT, headers).into_response()
+}
+
+fn check_rate_limit(state: &AppState, key: &'static str) -> Result<(), Duration> {
+    state
+        .refresh_limiter
+        .try_refresh(key, state.config.server.refresh_cooldown(), Utc::now())
+}
+
+async fn refresh_sources(state: &AppState, sources: Vec<CacheSource>) -> anyhow::Result<()> {
+    let handles: Vec<_> = sources
+        .into_iter()
+        .map(|source| tokio::spawn(source.refresh(state.clone())))
+        .collect();
+
+    // wait for every source, even after a failure, so the caller only
+    // re-renders once all the refreshes have been written
+    let mut result = Ok(());
+
+    for handle in handles {
+        let refresh_result = handle.await.map_err(anyhow::Error::from).and_then(|r| r);
+
+        if result.is_ok() {
+            result = refresh_result;
+        }
+    }
+
+    result
+}
+
+fn too_many_requests(remaining: Duration) -> Response {
+    // round up so clients never retry while still inside the cooldown
+    let retry_after_seconds = (remaining.num_milliseconds() + 999) / 1000;
+
+    (
+        StatusCode::TOO_MANY_REQUESTS,
+        [(header::RETRY_AFTER, retry_after_seconds.to_string())],
+        Html(format!(
+            "Refreshed too recently, try again in {retry_after_seconds} seconds"
+        )),
+    )
+        .into_response()
+}
+
+#[cfg(test)]
+mod test_super {
+    use chrono::TimeZone;
+
+    use super::*;
+
+    #[test]
+    fn test_refresh_limiter_refuses_within_cooldown() {
+        let limiter = RefreshLimiter::default();
+        let now = Utc.with_ymd_and_hms(2024, 6, 21, 10, 0, 0).unwrap();
+        let cooldown = Duration::seconds(60);
+
+        assert!(limiter.try_refresh("wanikani", cooldown, now).is_ok());
+        assert_eq!(
+            limiter.try_refresh("wanikani", cooldown, now + Duration::seconds(20)),
+            Err(Duration::seconds(40))
+        );
+        assert!(limiter.try_refresh("bunpro", cooldown, now).is_ok());
+        assert!(limiter
+            .try_refresh("wanikani", cooldown, now + Duration::seconds(60))
+            .is_ok());
+    }
+
+    #[test]
+    fn test_too_many_requests_rounds_retry_after_up() {
+        let response = too_many_requests(Duration::milliseconds(1500));
+
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(response.headers()[header::RETRY_AFTER], "2");
+    }
+}
@@ -0,0 +1,146 @@
+use std::{future::Future, pin::Pin, str::FromStr};
+
+use anyhow::anyhow;
+use axum::{
+    routing::{get, post},
+    Router,
+};
+
+use crate::{
+    api::{
+        anki::data::AnkiData,
+        bunpro::data::BunproData,
+        cacheable::CacheSource,
+        refresh::refresh_handler,
+        satori::data::SatoriData,
+        study_service::{html_handler, json_handler, StudyService},
+        summary::ServiceSummary,
+        wanikani::data::WanikaniData,
+    },
+    state::AppState,
+};
+
+type SummaryFuture = Pin<Box<dyn Future<Output = ServiceSummary> + Send>>;
+
+#[derive(Clone, Copy, Debug, PartialEq, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ServiceId {
+    Wanikani,
+    Bunpro,
+    Satori,
+    Anki,
+}
+
+impl ServiceId {
+    pub const ALL: [ServiceId; 4] = [
+        ServiceId::Wanikani,
+        ServiceId::Bunpro,
+        ServiceId::Satori,
+        ServiceId::Anki,
+    ];
+
+    fn entry(self) -> ServiceEntry {
+        match self {
+            ServiceId::Wanikani => ServiceEntry::new::<WanikaniData>(self),
+            ServiceId::Bunpro => ServiceEntry::new::<BunproData>(self),
+            ServiceId::Satori => ServiceEntry::new::<SatoriData>(self),
+            ServiceId::Anki => ServiceEntry::new::<AnkiData>(self),
+        }
+    }
+}
+
+impl FromStr for ServiceId {
+    type Err = anyhow::Error;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.trim().to_lowercase().as_str() {
+            "wanikani" => Ok(Self::Wanikani),
+            "bunpro" => Ok(Self::Bunpro),
+            "satori" => Ok(Self::Satori),
+            "anki" => Ok(Self::Anki),
+            unknown => Err(anyhow!("Unknown study service: {unknown}")),
+        }
+    }
+}
+
+/// Type erased view of a `StudyService`, so the enabled services can be
+/// stored together and looped over
+#[derive(Clone)]
+pub struct ServiceEntry {
+    pub service_id: ServiceId,
+    pub id: &'static str,
+    pub display_name: &'static str,
+    pub sources: fn() -> Vec<CacheSource>,
+    routes: fn(Router<AppState>) -> Router<AppState>,
+    summarize: fn(AppState) -> SummaryFuture,
+}
+
+impl ServiceEntry {
+    fn new<S: StudyService>(service_id: ServiceId) -> Self {
+        Self {
+            service_id,
+            id: S::ID,
+            display_name: S::DISPLAY_NAME,
+            sources: S::sources,
+            routes: service_routes::<S>,
+            summarize: summarize::<S>,
+        }
+    }
+
+    pub fn summarize(&self, state: AppState) -> SummaryFuture {
+        (self.summarize)(state)
+    }
+}
+
+fn service_routes<S: StudyService>(router: Router<AppState>) -> Router<AppState> {
+    S::extra_routes(router)
+        .route(&format!("/{}", S::ID), get(html_handler::<S>))
+        .route(&format!("/{}/refresh", S::ID), post(refresh_handler::<S>))
+        .route(&format!("/api/v1/{}", S::ID), get(json_handler::<S>))
+}
+
+fn summarize<S: StudyService>(state: AppState) -> SummaryFuture {
+    Box::pin(async move {
+        let result = S::fetch(&state)
+            .await
+            .map(|(data, expiry)| (data.study_counts(), data.goal_status(), expiry));
+
+        ServiceSummary::from_result(S::ID, result)
+    })
+}
+
+pub struct Registry {
+    services: Vec<ServiceEntry>,
+}
+
+impl Registry {
+    pub fn new(service_ids: &[ServiceId]) -> Self {
+        Self {
+            services: service_ids.iter().map(|id| id.entry()).collect(),
+        }
+    }
+
+    pub fn services(&self) -> &[ServiceEntry] {
+        &self.services
+    }
+
+    pub fn routes(&self) -> Router<AppState> {
+        self.services
+            .iter()
+            .fold(Router::new(), |router, service| (service.routes)(router))
+    }
+}
+
+#[cfg(test)]
+mod test_super {
+    use super::*;
+
+    #[test]
+    fn test_registry_keeps_configured_order() {
+        let registry = Registry::new(&[ServiceId::Satori, ServiceId::Wanikani]);
+
+        let ids: Vec<&str> = registry.services().iter().map(|s| s.id).collect();
+
+        assert_eq!(ids, vec!["satori", "wanikani"]);
+    }
+}
@@ -1,76 +1,270 @@
 use askama::Template;
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, Duration, NaiveDate, Utc};
+
+use crate::api::history::{current_streak, week_start, Heatmap};
+
+/// Weeks of score totals shown on the card, including the current one
+const SCORE_TOTAL_WEEKS: i64 = 4;
 
 #[derive(serde::Serialize, Template)]
 #[template(path = "satori.html")]
 pub struct SatoriData {
     data_updated_at: DateTime<Utc>,
-    active_review_count: u32,
-    new_card_count: u32,
-    daily_study_goal_met: bool,
+    pub active_review_count: u32,
+    pub new_card_count: u32,
+    pub daily_study_goal_met: bool,
+    /// Why today's activity isn't known, or there is none
+    pub stats_issue: Option<SatoriScrapeError>,
+    pub heat_level_goal: HeatLevelGoal,
+    pub heat4_streak: u32,
+    pub weekly_scores: Vec<WeeklyScore>,
+    pub today: NaiveDate,
+    pub heat_history: Vec<SatoriHeatData>,
+    pub stale_since: Option<DateTime<Utc>>,
 }
 
 impl SatoriData {
     pub fn new(
         current_cards: SatoriCurrentCardsResponse,
         new_cards: SatoriNewCardsResponse,
-        stats: SatoriStats,
+        stats: Result<SatoriStats, SatoriScrapeError>,
+        target_level: SatoriHeatLevel,
+        today: NaiveDate,
     ) -> Self {
+        let (stats, stats_issue) = match stats {
+            Ok(stats) if stats.has_activity_on(today) => (stats, None),
+            Ok(stats) => (stats, Some(SatoriScrapeError::NoActivityToday)),
+            Err(err) => (SatoriStats::default(), Some(err)),
+        };
+
+        let heat_level_goal = HeatLevelGoal {
+            target_level,
+            score: stats.score_on(today),
+            threshold: target_level.threshold(),
+        };
+
         Self {
             data_updated_at: current_cards.fetched_at.unwrap_or(Utc::now()),
             active_review_count: current_cards.result,
             new_card_count: new_cards.result,
-            daily_study_goal_met: stats.heat_level == SatoriHeatLevel::Four,
+            daily_study_goal_met: heat_level_goal.met(),
+            stats_issue,
+            heat_level_goal,
+            heat4_streak: current_streak(today, |date| {
+                stats.heat_level_on(date) == SatoriHeatLevel::Four
+            }),
+            weekly_scores: stats.weekly_scores(today),
+            today,
+            heat_history: stats.history,
+            stale_since: None,
         }
     }
+
+    fn heatmap(&self) -> Heatmap {
+        Heatmap::from_levels(self.today, |date| {
+            let day = self.heat_history.iter().find(|day| day.date == date);
+            let score = day.map_or(0.0, |day| day.score);
+
+            (
+                day.map_or(0, |day| day.heat_level.number()),
+                format!("{}: {score:.1} score", date.format("%Y-%m-%d")),
+            )
+        })
+    }
 }
 
-#[derive(serde::Deserialize, serde::Serialize)]
-pub struct SatoriCurrentCardsResponse {
-    result: u32,
+#[derive(serde::Serialize, Debug, PartialEq)]
+pub struct WeeklyScore {
+    pub week_start: NaiveDate,
+    pub total: f64,
+}
+
+/// Envelope every Satori api response comes in. Failures are reported with
+/// `success` false rather than an error status
+#[derive(serde::Deserialize)]
+pub struct SatoriApiResponse<T> {
+    result: Option<T>,
     success: bool,
     message: Option<String>,
     exception: Option<String>,
+}
+
+impl<T> SatoriApiResponse<T> {
+    pub fn into_result(self) -> anyhow::Result<T> {
+        if !self.success || self.exception.is_some() {
+            let reason = self
+                .exception
+                .or(self.message)
+                .unwrap_or_else(|| "no message given".to_string());
+
+            anyhow::bail!("Satori api request failed: {reason}");
+        }
+
+        self.result
+            .ok_or(anyhow::anyhow!("Satori api response is missing its result"))
+    }
+}
+
+#[derive(serde::Deserialize, serde::Serialize)]
+pub struct SatoriCurrentCardsResponse {
+    pub result: u32,
     pub fetched_at: Option<DateTime<Utc>>,
 }
 
 #[derive(serde::Deserialize, serde::Serialize)]
 pub struct SatoriNewCardsResponse {
-    result: u32,
-    success: bool,
-    message: Option<String>,
-    exception: Option<String>,
+    pub result: u32,
 }
 
-#[derive(serde::Serialize, serde::Deserialize)]
+/// Satori's daily activity scores, as shown in its dashboard heatmap
+#[derive(serde::Serialize, serde::Deserialize, Default, Debug)]
 pub struct SatoriStats {
+    pub history: Vec<SatoriHeatData>,
+}
+
+/// Why Satori's activity for today couldn't be shown
+#[derive(serde::Serialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum SatoriScrapeError {
+    /// The dashboard loaded but the activity scores weren't where expected
+    MarkupChanged,
+    /// The scores were read but there are none for today yet
+    NoActivityToday,
+}
+
+impl std::fmt::Display for SatoriScrapeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let message = match self {
+            Self::MarkupChanged => "Satori's dashboard has changed, activity couldn't be read",
+            Self::NoActivityToday => "No Satori activity today",
+        };
+
+        f.write_str(message)
+    }
+}
+
+impl std::error::Error for SatoriScrapeError {}
+
+impl SatoriStats {
+    fn has_activity_on(&self, date: NaiveDate) -> bool {
+        self.history.iter().any(|day| day.date == date)
+    }
+
+    pub fn score_on(&self, date: NaiveDate) -> f64 {
+        self.history
+            .iter()
+            .find(|day| day.date == date)
+            .map_or(0.0, |day| day.score)
+    }
+
+    pub fn heat_level_on(&self, date: NaiveDate) -> SatoriHeatLevel {
+        SatoriHeatLevel::from_score(self.score_on(date))
+    }
+
+    /// Score totals for the weeks up to and including the current one, most
+    /// recent first
+    pub fn weekly_scores(&self, today: NaiveDate) -> Vec<WeeklyScore> {
+        (0..SCORE_TOTAL_WEEKS)
+            .map(|weeks_ago| {
+                let start = week_start(today) - Duration::weeks(weeks_ago);
+                let total: f64 = start
+                    .iter_days()
+                    .take(7)
+                    .take_while(|date| *date <= today)
+                    .map(|date| self.score_on(date))
+                    .sum();
+
+                WeeklyScore {
+                    week_start: start,
+                    total: (total * 10.0).round() / 10.0,
+                }
+            })
+            .collect()
+    }
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug, PartialEq)]
+pub struct SatoriHeatData {
+    pub date: NaiveDate,
+    pub score: f64,
     pub heat_level: SatoriHeatLevel,
 }
 
 #[derive(serde::Deserialize)]
-pub struct SatoriHeatData {
-    pub date: String,
+pub struct SatoriActivityScore {
+    pub date: NaiveDate,
     pub score: f64,
 }
 
-impl SatoriHeatData {
-    pub fn heat_level(&self) -> SatoriHeatLevel {
+impl From<SatoriActivityScore> for SatoriHeatData {
+    fn from(activity_score: SatoriActivityScore) -> Self {
+        Self {
+            date: activity_score.date,
+            score: activity_score.score,
+            heat_level: SatoriHeatLevel::from_score(activity_score.score),
+        }
+    }
+}
+
+impl SatoriHeatLevel {
+    const ABOVE_ZERO: [Self; 4] = [Self::Four, Self::Three, Self::Two, Self::One];
+
+    pub fn from_score(score: f64) -> Self {
+        Self::ABOVE_ZERO
+            .into_iter()
+            .find(|level| score > level.threshold())
+            .unwrap_or(Self::Zero)
+    }
+
+    /// Level 1 to 4, as set in `satori.target_heat_level`
+    pub fn from_number(level: u32) -> Option<Self> {
+        Self::ABOVE_ZERO
+            .into_iter()
+            .find(|heat_level| heat_level.number() == level)
+    }
+
+    pub fn number(self) -> u32 {
+        self as u32
+    }
+
+    /// Score a day needs to be above to reach the level
+    pub fn threshold(self) -> f64 {
         // HeatMap levels taken from Satori frontend js file
-        if self.score > 9.0 {
-            SatoriHeatLevel::Four
-        } else if self.score > 3.0 {
-            SatoriHeatLevel::Three
-        } else if self.score > 1.0 {
-            SatoriHeatLevel::Two
-        } else if self.score > 0.0 {
-            SatoriHeatLevel::One
+        match self {
+            Self::Four => 9.0,
+            Self::Three => 3.0,
+            Self::Two => 1.0,
+            Self::One => 0.0,
+            Self::Zero => f64::NEG_INFINITY,
+        }
+    }
+}
+
+/// Today's score against the score needed for the target heat level
+#[derive(serde::Serialize, Debug, PartialEq)]
+pub struct HeatLevelGoal {
+    pub target_level: SatoriHeatLevel,
+    pub score: f64,
+    pub threshold: f64,
+}
+
+impl HeatLevelGoal {
+    pub fn met(&self) -> bool {
+        self.score > self.threshold
+    }
+
+    pub fn progress_percentage(&self) -> u32 {
+        if self.met() {
+            100
+        } else if self.threshold <= 0.0 {
+            0
         } else {
-            SatoriHeatLevel::Zero
+            (self.score / self.threshold * 100.0) as u32
         }
     }
 }
 
-#[derive(serde::Serialize, serde::Deserialize, PartialEq, Debug)]
+#[derive(serde::Serialize, serde::Deserialize, Clone, Copy, PartialEq, PartialOrd, Debug)]
 pub enum SatoriHeatLevel {
     Zero,
     One,
@@ -1,46 +1,150 @@
-use std::env;
-
-use askama::Template;
-use axum::{extract::State, http::HeaderMap, response::Html};
+use chrono::{DateTime, Utc};
+use chrono_tz::Asia::Tokyo;
 use reqwest::Client;
+use scraper::{Html, Selector};
+use serde::de::DeserializeOwned;
 use tokio::try_join;
 
-use crate::api::{add_expiry_header, cacheable::Cacheable, internal_error, HtmlErrorResponse};
+use crate::{
+    api::{
+        cacheable::{earliest_stale_since, CacheSource, Cacheable, Cached},
+        earliest_expiry,
+        study_service::{GoalStatus, StudyCounts, StudyService},
+        SessionExpired,
+    },
+    config::{credential, SatoriConfig},
+    state::AppState,
+};
 
-use super::data::{SatoriCurrentCardsResponse, SatoriData, SatoriNewCardsResponse, SatoriStats};
+use super::data::{
+    SatoriApiResponse, SatoriCurrentCardsResponse, SatoriData, SatoriHeatLevel,
+    SatoriNewCardsResponse, SatoriScrapeError, SatoriStats,
+};
 
 mod current_cards;
 mod new_cards;
 mod stats;
 
-pub async fn satori_handler(
-    State(redis_client): State<Option<redis::Client>>,
-) -> Result<(HeaderMap, Html<String>), HtmlErrorResponse> {
-    let (
-        (current_cards, current_cards_expiry),
-        (new_cards, new_cards_expiry),
-        (stats, stats_expiry),
-    ) = try_join!(
-        SatoriCurrentCardsResponse::get(&redis_client),
-        SatoriNewCardsResponse::get(&redis_client),
-        SatoriStats::get(&redis_client),
-    )
-    .map_err(internal_error)?;
-
-    let satori_data = SatoriData::new(current_cards, new_cards, stats);
-
-    let headers = add_expiry_header(
-        HeaderMap::new(),
-        &[current_cards_expiry, new_cards_expiry, stats_expiry],
-    );
+impl StudyService for SatoriData {
+    const ID: &'static str = "satori";
+    const DISPLAY_NAME: &'static str = "Satori";
+
+    fn sources() -> Vec<CacheSource> {
+        vec![
+            CacheSource::of::<SatoriCurrentCardsResponse>(),
+            CacheSource::of::<SatoriNewCardsResponse>(),
+            CacheSource::of::<SatoriStats>(),
+        ]
+    }
+
+    async fn fetch(state: &AppState) -> anyhow::Result<(Self, Option<DateTime<Utc>>)> {
+        let (current_cards, new_cards, stats) = try_join!(
+            SatoriCurrentCardsResponse::get(state),
+            SatoriNewCardsResponse::get(state),
+            scraped_stats(state),
+        )?;
+
+        let (stats, stats_stale_since, stats_expires_at) = match stats {
+            Ok(cached) => (Ok(cached.data), cached.stale_since, cached.expires_at),
+            Err(err) => (Err(err), None, None),
+        };
+
+        let today = Utc::now().with_timezone(&Tokyo).date_naive();
+        let target_level = SatoriHeatLevel::from_number(state.config.satori.target_heat_level)
+            .unwrap_or(SatoriHeatLevel::Four);
+        let mut satori_data = SatoriData::new(
+            current_cards.data,
+            new_cards.data,
+            stats,
+            target_level,
+            today,
+        );
+        satori_data.stale_since = earliest_stale_since(&[
+            current_cards.stale_since,
+            new_cards.stale_since,
+            stats_stale_since,
+        ]);
+        let expires_at = earliest_expiry(&[
+            current_cards.expires_at,
+            new_cards.expires_at,
+            stats_expires_at,
+        ]);
+
+        Ok((satori_data, expires_at))
+    }
+
+    fn study_counts(&self) -> StudyCounts {
+        StudyCounts {
+            active_review_count: self.active_review_count,
+            new_item_count: self.new_card_count,
+        }
+    }
+
+    fn goal_status(&self) -> GoalStatus {
+        GoalStatus::from(self.daily_study_goal_met)
+    }
+}
+
+/// The activity scores, with scraping failures kept apart so the rest of the
+/// card can still be shown. Other errors, like network failures or an expired
+/// session, are returned
+async fn scraped_stats(
+    state: &AppState,
+) -> anyhow::Result<Result<Cached<SatoriStats>, SatoriScrapeError>> {
+    match SatoriStats::get(state).await {
+        Ok(cached) => Ok(Ok(cached)),
+        Err(err) => {
+            let scrape_error = err.downcast::<SatoriScrapeError>()?;
+            tracing::warn!("Unable to scrape Satori activity: {scrape_error}");
+
+            Ok(Err(scrape_error))
+        }
+    }
+}
+
+/// Fetches a Satori api endpoint and unwraps the envelope it comes in
+async fn get_api_result<T: DeserializeOwned>(
+    config: &SatoriConfig,
+    url: &str,
+) -> anyhow::Result<T> {
+    let response = satori_client(config)?
+        .get(url)
+        .send()
+        .await?
+        .error_for_status()?;
+
+    let final_url = response.url().to_string();
+    let body = response.text().await?;
 
-    let html_string = satori_data.render().map_err(internal_error)?;
+    parse_api_response(&final_url, &body)
+}
+
+fn parse_api_response<T: DeserializeOwned>(url: &str, body: &str) -> anyhow::Result<T> {
+    // an expired session is sent to the sign in page instead of getting json
+    if body.trim_start().starts_with('<') {
+        if is_login_page(url, &Html::parse_document(body)) {
+            return Err(SessionExpired { service: "Satori" }.into());
+        }
+
+        anyhow::bail!("Satori api returned html instead of json");
+    }
+
+    serde_json::from_str::<SatoriApiResponse<T>>(body)?.into_result()
+}
+
+/// Whether the page at `url`, where a request ended up after redirects, is
+/// Satori's sign in page
+fn is_login_page(url: &str, document: &Html) -> bool {
+    let password_input = Selector::parse("input[type=\"password\"]").unwrap();
+    let path = url.split('?').next().unwrap_or(url).to_lowercase();
 
-    Ok((headers, Html(html_string)))
+    path.contains("signin")
+        || path.contains("login")
+        || document.select(&password_input).next().is_some()
 }
 
-pub fn satori_client() -> anyhow::Result<Client> {
-    let satori_cookie = env::var("SATORI_COOKIE")?;
+pub fn satori_client(config: &SatoriConfig) -> anyhow::Result<Client> {
+    let satori_cookie = credential(&config.cookie, "Satori cookie")?;
 
     let mut headers = reqwest::header::HeaderMap::new();
     headers.insert(
@@ -50,3 +154,44 @@ pub fn satori_client() -> anyhow::Result<Client> {
 
     Ok(Client::builder().default_headers(headers).build()?)
 }
+
+#[cfg(test)]
+mod test_super {
+    use super::*;
+
+    const URL: &str = "https://www.satorireader.com/api/studylist/due/count";
+
+    #[test]
+    fn test_api_failure_uses_upstream_exception() {
+        let body = include_str!("./fixtures/api_exception.json");
+        let err = parse_api_response::<u32>(URL, body).unwrap_err();
+
+        assert!(err
+            .to_string()
+            .contains("System.UnauthorizedAccessException"));
+    }
+
+    #[test]
+    fn test_api_failure_falls_back_to_message() {
+        let body = include_str!("./fixtures/api_failure_with_message.json");
+        let err = parse_api_response::<u32>(URL, body).unwrap_err();
+
+        assert_eq!(
+            err.to_string(),
+            "Satori api request failed: Study list is temporarily unavailable."
+        );
+    }
+
+    #[test]
+    fn test_login_page_is_session_expired() {
+        let body = include_str!("./fixtures/dashboard_logged_out.html");
+        let err = parse_api_response::<u32>(
+            "https://www.satorireader.com/signin?returnUrl=%2Fapi%2Fstudylist%2Fdue%2Fcount",
+            body,
+        )
+        .unwrap_err();
+
+        assert!(err.downcast_ref::<SessionExpired>().is_some());
+        assert_eq!(err.to_string(), "Satori session expired");
+    }
+}
@@ -1,61 +1,52 @@
-use chrono::{DateTime, Duration, Utc};
-
-use crate::api::{
-    cacheable::{CacheKey, Cacheable},
-    satori::data::SatoriNewCardsResponse,
+use crate::{
+    api::{
+        cacheable::{CacheKey, Cacheable},
+        satori::data::SatoriNewCardsResponse,
+    },
+    config::{Config, SatoriConfig},
 };
 
-use super::satori_client;
+use super::get_api_result;
 
 impl Cacheable for SatoriNewCardsResponse {
     fn cache_key() -> CacheKey {
         CacheKey::SatoriNewCards
     }
 
-    fn expires_at() -> DateTime<Utc> {
-        Utc::now() + Duration::hours(1)
-    }
-
-    async fn api_fetch() -> anyhow::Result<Self> {
-        get_new_cards().await
+    async fn api_fetch(config: &Config) -> anyhow::Result<Self> {
+        get_new_cards(&config.satori).await
     }
 }
 
-async fn get_new_cards() -> anyhow::Result<SatoriNewCardsResponse> {
-    let client = satori_client()?;
+async fn get_new_cards(config: &SatoriConfig) -> anyhow::Result<SatoriNewCardsResponse> {
+    let result = get_api_result(
+        config,
+        "https://www.satorireader.com/api/studylist/pending-auto-importable/count",
+    )
+    .await?;
 
-    client
-        .get("https://www.satorireader.com/api/studylist/pending-auto-importable/count")
-        .send()
-        .await?
-        .text()
-        .await
-        .map(|body| serialize_new_cards_response(&body))?
-}
-
-fn serialize_new_cards_response(body: &str) -> anyhow::Result<SatoriNewCardsResponse> {
-    let json_data: SatoriNewCardsResponse = serde_json::from_str(body)?;
-
-    Ok(json_data)
+    Ok(SatoriNewCardsResponse { result })
 }
 
 #[cfg(test)]
 mod test_super {
-    use super::*;
+    use super::super::parse_api_response;
+
+    const URL: &str = "https://www.satorireader.com/api/studylist/pending-auto-importable/count";
 
     #[test]
     fn test_new_card_with_pending_cards() {
         let json_string = include_str!("../fixtures/new_cards_with_pending_cards.json");
-        let serialized_result = serialize_new_cards_response(json_string);
+        let result = parse_api_response::<u32>(URL, json_string);
 
-        assert!(serialized_result.is_ok());
+        assert_eq!(result.unwrap(), 20);
     }
 
     #[test]
     fn test_new_card_with_no_cards() {
         let json_string = include_str!("../fixtures/new_cards_with_no_cards.json");
-        let serialized_result = serialize_new_cards_response(json_string);
+        let result = parse_api_response::<u32>(URL, json_string);
 
-        assert!(serialized_result.is_ok());
+        assert!(result.is_ok());
     }
 }
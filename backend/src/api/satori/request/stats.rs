@@ -1,14 +1,17 @@
-use anyhow::anyhow;
-use chrono::{DateTime, Duration, Utc};
+use chrono::{DateTime, Utc};
 use regex::Regex;
-use scraper::{ElementRef, Html, Selector};
-
-use crate::api::{
-    cacheable::{CacheKey, Cacheable},
-    satori::{
-        data::{SatoriHeatData, SatoriHeatLevel, SatoriStats},
-        request::satori_client,
+use scraper::{Html, Selector};
+
+use crate::{
+    api::{
+        cacheable::{expires_after, next_jst_midnight, CacheKey, Cacheable},
+        satori::{
+            data::{SatoriActivityScore, SatoriHeatData, SatoriScrapeError, SatoriStats},
+            request::{is_login_page, satori_client},
+        },
+        SessionExpired,
     },
+    config::Config,
 };
 
 impl Cacheable for SatoriStats {
@@ -16,133 +19,272 @@ impl Cacheable for SatoriStats {
         CacheKey::SatoriStats
     }
 
-    fn expires_at() -> DateTime<Utc> {
-        Utc::now() + Duration::hours(1)
-    }
-
-    async fn api_fetch() -> anyhow::Result<Self> {
-        let client = satori_client()?;
+    async fn api_fetch(config: &Config) -> anyhow::Result<Self> {
+        let client = satori_client(&config.satori)?;
 
-        let html = client
+        let response = client
             .get("https://www.satorireader.com/dashboard")
             .header(reqwest::header::ACCEPT, "text/html")
             .send()
             .await?
-            .error_for_status()?
-            .text()
-            .await?;
+            .error_for_status()?;
 
-        let document = Html::parse_document(html.as_str());
+        let final_url = response.url().to_string();
+        let html = response.text().await?;
 
-        let heatmap_js_selector = Selector::parse("script[type=\"text/javascript\"]").unwrap();
-        let elements: Vec<_> = document.select(&heatmap_js_selector).collect();
-
-        if elements.len() != 1 {
-            anyhow::bail!(format!(
-                "Expected to find 1 element, found {} elements",
-                elements.len()
-            ))
-        }
+        scrape_dashboard(&final_url, &html)
+    }
 
-        let heat_data_json = extract_heat_data_from_js(elements.first().unwrap())?;
+    fn expires_at(&self, config: &Config) -> DateTime<Utc> {
+        expires_after(Some(next_jst_midnight(Utc::now())), config)
+    }
+}
 
-        let todays_heat_level = todays_heat_level(heat_data_json, None);
+/// Reads the activity scores out of the dashboard at `url`, which is where
+/// the request ended up after any redirects. Being sent to sign in is a
+/// `SessionExpired`, anything else wrong with the page a `SatoriScrapeError`
+fn scrape_dashboard(url: &str, html: &str) -> anyhow::Result<SatoriStats> {
+    let document = Html::parse_document(html);
 
-        Ok(Self {
-            heat_level: todays_heat_level,
-        })
+    if is_login_page(url, &document) {
+        return Err(SessionExpired { service: "Satori" }.into());
     }
-}
 
-fn date_for_heatmap(date: Option<DateTime<Utc>>) -> String {
-    let date = date
-        .unwrap_or(Utc::now())
-        .with_timezone(&chrono_tz::Asia::Tokyo);
+    let heat_data_json =
+        extract_heat_data_from_js(&document).ok_or(SatoriScrapeError::MarkupChanged)?;
+    let history =
+        deserialize_heat_data(&heat_data_json).map_err(|_| SatoriScrapeError::MarkupChanged)?;
 
-    date.format("%Y-%m-%d").to_string()
+    Ok(SatoriStats { history })
 }
 
-fn extract_heat_data_from_js(element: &ElementRef) -> anyhow::Result<String> {
-    let re = Regex::new("var activityScores = (.+);")?;
+/// Searches every script on the page, so scripts being split up, reordered
+/// or retyped doesn't lose the scores
+fn extract_heat_data_from_js(document: &Html) -> Option<String> {
+    let script_selector = Selector::parse("script").unwrap();
+    // the array assigned to `activityScores`, however it's declared and spaced
+    let re = Regex::new(r"(?s)activityScores\s*=\s*(\[.*?\])\s*;").unwrap();
 
-    let javascript = element.inner_html();
-    let heat_data = re
-        .captures(javascript.as_str())
-        .and_then(|captures| captures.get(1))
-        .ok_or(anyhow!("Unable to find heat data json"))?;
+    document.select(&script_selector).find_map(|script| {
+        let javascript = script.inner_html();
 
-    Ok(heat_data.as_str().to_string())
+        re.captures(&javascript)
+            .and_then(|captures| captures.get(1))
+            .map(|heat_data| heat_data.as_str().to_string())
+    })
 }
 
 fn deserialize_heat_data(json_data: &str) -> anyhow::Result<Vec<SatoriHeatData>> {
-    let heat_data: Vec<SatoriHeatData> = serde_json::from_str(json_data)?;
-
-    Ok(heat_data)
-}
-
-fn todays_heat_level(heat_data_json: String, date: Option<DateTime<Utc>>) -> SatoriHeatLevel {
-    let todays_date = date_for_heatmap(date);
+    let activity_scores: Vec<SatoriActivityScore> = serde_json::from_str(json_data)?;
 
-    deserialize_heat_data(&heat_data_json)
-        .ok()
-        .and_then(|heat_data| heat_data.into_iter().find(|hd| hd.date == todays_date))
-        .map_or(SatoriHeatLevel::Zero, |today| today.heat_level())
+    Ok(activity_scores
+        .into_iter()
+        .map(SatoriHeatData::from)
+        .collect())
 }
 
 #[cfg(test)]
 mod test_super {
-    use chrono::TimeZone;
+    use chrono::NaiveDate;
     use std::str::FromStr;
 
     use super::*;
+    use crate::api::{
+        history::current_streak,
+        satori::data::{HeatLevelGoal, SatoriData, SatoriHeatLevel, WeeklyScore},
+    };
+
+    const DASHBOARD_URL: &str = "https://www.satorireader.com/dashboard";
 
     #[test]
     fn test_extract_heat_data_from_js() {
         let html = include_str!("../fixtures/dashboard_minimal.html");
         let document = Html::parse_document(html);
-        let heatmap_js_selector = Selector::parse("script[type=\"text/javascript\"]").unwrap();
-        let elements: Vec<_> = document.select(&heatmap_js_selector).collect();
 
-        let heatmap_data = extract_heat_data_from_js(elements.first().unwrap());
+        let heatmap_data = extract_heat_data_from_js(&document);
         let expected_data =
             r#"[{"userID":"[REDACTED]","date":"2023-04-18","score":9.39999999999999}]"#;
 
-        assert!(heatmap_data.is_ok());
-        assert_eq!(heatmap_data.unwrap(), expected_data);
+        assert_eq!(heatmap_data.as_deref(), Some(expected_data));
     }
 
     #[test]
-    fn test_todays_heat_level_with_day_defined() {
-        let heat_data_json = String::from(
-            r#"[{"userID":"[REDACTED]","date":"2023-04-18","score":9.39999999999999}]"#,
+    fn test_scrape_dashboard_searches_every_script() {
+        let html = include_str!("../fixtures/dashboard_split_scripts.html");
+        let stats = scrape_dashboard(DASHBOARD_URL, html).unwrap();
+
+        assert_eq!(stats.history.len(), 2);
+        assert_eq!(
+            stats.history[1].date,
+            NaiveDate::from_str("2023-04-19").unwrap()
         );
+    }
+
+    fn scrape_error(url: &str, html: &str) -> Option<SatoriScrapeError> {
+        scrape_dashboard(url, html)
+            .unwrap_err()
+            .downcast_ref::<SatoriScrapeError>()
+            .copied()
+    }
+
+    #[test]
+    fn test_scrape_dashboard_detects_login_redirect() {
+        let html = include_str!("../fixtures/dashboard_logged_out.html");
+
+        let err = scrape_dashboard(
+            "https://www.satorireader.com/signin?returnUrl=%2Fdashboard",
+            html,
+        )
+        .unwrap_err();
+        assert!(err.downcast_ref::<SessionExpired>().is_some());
+
+        // the sign in form is recognised even without a redirect
+        let err = scrape_dashboard(DASHBOARD_URL, html).unwrap_err();
+        assert_eq!(err.to_string(), "Satori session expired");
+    }
+
+    #[test]
+    fn test_scrape_dashboard_reports_markup_changes() {
+        let html = include_str!("../fixtures/dashboard_markup_changed.html");
+
+        assert_eq!(
+            scrape_error(DASHBOARD_URL, html),
+            Some(SatoriScrapeError::MarkupChanged)
+        );
+        assert_eq!(
+            scrape_error(
+                DASHBOARD_URL,
+                "<script>var activityScores = [{\"date\": \"yesterday\"}];</script>"
+            ),
+            Some(SatoriScrapeError::MarkupChanged)
+        );
+    }
 
-        let date = Some(DateTime::<Utc>::from_str("2023-04-18 00:00:00+00:00").unwrap());
-        let heat_level = todays_heat_level(heat_data_json, date);
+    #[test]
+    fn test_heat_level_on_with_day_defined() {
+        let stats = SatoriStats {
+            history: deserialize_heat_data(
+                r#"[{"userID":"[REDACTED]","date":"2023-04-18","score":9.39999999999999}]"#,
+            )
+            .unwrap(),
+        };
+
+        let date = NaiveDate::from_str("2023-04-18").unwrap();
+
+        assert_eq!(stats.heat_level_on(date), SatoriHeatLevel::Four);
+    }
+
+    #[test]
+    fn test_heat_level_on_with_day_missing() {
+        let stats = SatoriStats {
+            history: deserialize_heat_data(
+                r#"[{"userID":"[REDACTED]","date":"2023-04-18","score":9.39999999999999}]"#,
+            )
+            .unwrap(),
+        };
+
+        let date = NaiveDate::from_str("2023-04-19").unwrap();
+
+        assert_eq!(stats.heat_level_on(date), SatoriHeatLevel::Zero);
+    }
+
+    #[test]
+    fn test_heat_level_thresholds() {
+        assert_eq!(SatoriHeatLevel::from_score(9.0), SatoriHeatLevel::Three);
+        assert_eq!(SatoriHeatLevel::from_score(9.1), SatoriHeatLevel::Four);
+        assert_eq!(SatoriHeatLevel::from_score(0.0), SatoriHeatLevel::Zero);
+        assert_eq!(SatoriHeatLevel::from_number(2), Some(SatoriHeatLevel::Two));
+        assert_eq!(SatoriHeatLevel::from_number(0), None);
+    }
 
-        assert_eq!(heat_level, SatoriHeatLevel::Four);
+    #[test]
+    fn test_heat_level_goal_progress() {
+        let goal = HeatLevelGoal {
+            target_level: SatoriHeatLevel::Four,
+            score: 6.3,
+            threshold: SatoriHeatLevel::Four.threshold(),
+        };
+
+        assert!(!goal.met());
+        assert_eq!(goal.progress_percentage(), 70);
+
+        let lower_goal = HeatLevelGoal {
+            target_level: SatoriHeatLevel::Three,
+            threshold: SatoriHeatLevel::Three.threshold(),
+            ..goal
+        };
+
+        assert!(lower_goal.met());
+        assert_eq!(lower_goal.progress_percentage(), 100);
     }
 
     #[test]
-    fn test_todays_heat_level_with_day_missing() {
-        let heat_data_json = String::from(
-            r#"[{"userID":"[REDACTED]","date":"2023-04-18","score":9.39999999999999}]"#,
+    fn test_heat4_streak_and_weekly_scores() {
+        let stats = SatoriStats {
+            history: deserialize_heat_data(
+                r#"[
+                    {"date":"2023-04-10","score":2.0},
+                    {"date":"2023-04-15","score":12.5},
+                    {"date":"2023-04-16","score":9.5},
+                    {"date":"2023-04-17","score":4.0},
+                    {"date":"2023-04-18","score":10.0}
+                ]"#,
+            )
+            .unwrap(),
+        };
+
+        // a Wednesday, with today not yet at heat4
+        let today = NaiveDate::from_str("2023-04-19").unwrap();
+
+        assert_eq!(
+            current_streak(today, |date| stats.heat_level_on(date)
+                == SatoriHeatLevel::Four),
+            1
+        );
+
+        let weekly_scores = stats.weekly_scores(today);
+        assert_eq!(weekly_scores.len(), 4);
+        assert_eq!(
+            weekly_scores[0],
+            WeeklyScore {
+                week_start: NaiveDate::from_str("2023-04-17").unwrap(),
+                total: 14.0
+            }
         );
+        assert_eq!(weekly_scores[1].total, 24.0);
+    }
 
-        let date = Some(DateTime::<Utc>::from_str("2023-04-19 00:00:00+00:00").unwrap());
-        let heat_level = todays_heat_level(heat_data_json, date);
+    #[test]
+    fn test_satori_data_reports_no_activity_today() {
+        let html = include_str!("../fixtures/dashboard_minimal.html");
+        let current_cards = serde_json::from_str(include_str!(
+            "../fixtures/current_cards_with_no_reviews.json"
+        ))
+        .unwrap();
+        let new_cards =
+            serde_json::from_str(include_str!("../fixtures/new_cards_with_no_cards.json")).unwrap();
+
+        let satori_data = SatoriData::new(
+            current_cards,
+            new_cards,
+            Ok(scrape_dashboard(DASHBOARD_URL, html).unwrap()),
+            SatoriHeatLevel::Four,
+            NaiveDate::from_str("2023-04-19").unwrap(),
+        );
 
-        assert_eq!(heat_level, SatoriHeatLevel::Zero);
+        assert_eq!(
+            satori_data.stats_issue,
+            Some(SatoriScrapeError::NoActivityToday)
+        );
+        assert!(!satori_data.daily_study_goal_met);
     }
 
     #[test]
     fn test_deserialize_heat_data() {
         let html = include_str!("../fixtures/dashboard_minimal.html");
         let document = Html::parse_document(html);
-        let heatmap_js_selector = Selector::parse("script[type=\"text/javascript\"]").unwrap();
-        let elements: Vec<_> = document.select(&heatmap_js_selector).collect();
 
-        let heatmap_data = extract_heat_data_from_js(elements.first().unwrap());
+        let heatmap_data = extract_heat_data_from_js(&document);
 
         let satori_heat_data = deserialize_heat_data(&heatmap_data.unwrap());
 
@@ -152,26 +294,10 @@ mod test_super {
         assert_eq!(satori_heat_data.len(), 1);
 
         let first_heat_data = satori_heat_data.first().unwrap();
-        assert_eq!(first_heat_data.date, "2023-04-18");
+        assert_eq!(
+            first_heat_data.date,
+            NaiveDate::from_str("2023-04-18").unwrap()
+        );
         assert_eq!(first_heat_data.score, 9.39999999999999);
     }
-
-    #[test]
-    fn test_date_for_heatmap() {
-        let early_date = chrono_tz::Asia::Tokyo
-            .with_ymd_and_hms(2023, 2, 12, 0, 0, 0)
-            .unwrap()
-            .with_timezone(&Utc);
-
-        let early_date_string = date_for_heatmap(Some(early_date));
-        assert_eq!(early_date_string, "2023-02-12");
-
-        let late_date = chrono_tz::Asia::Tokyo
-            .with_ymd_and_hms(2023, 10, 1, 0, 0, 0)
-            .unwrap()
-            .with_timezone(&Utc);
-
-        let late_date_string = date_for_heatmap(Some(late_date));
-        assert_eq!(late_date_string, "2023-10-01");
-    }
 }
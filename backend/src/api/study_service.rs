@@ -0,0 +1,84 @@
+use std::future::Future;
+
+use askama::Template;
+use axum::{extract::State, http::HeaderMap, response::Html, Json, Router};
+use chrono::{DateTime, Utc};
+
+use crate::{
+    api::{
+        add_expiry_header, cacheable::CacheSource, internal_error, json_internal_error,
+        ApiResponse, HtmlErrorResponse, JsonErrorResponse,
+    },
+    state::AppState,
+};
+
+/// Counts shared by every service, used to build the combined summary
+#[derive(serde::Serialize, Clone, Copy, Debug, PartialEq)]
+pub struct StudyCounts {
+    pub active_review_count: u32,
+    pub new_item_count: u32,
+}
+
+#[derive(serde::Serialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum GoalStatus {
+    Met,
+    NotMet,
+    /// The goal isn't being tracked, e.g. the account is on vacation
+    Suspended,
+}
+
+impl From<bool> for GoalStatus {
+    fn from(goal_met: bool) -> Self {
+        if goal_met {
+            GoalStatus::Met
+        } else {
+            GoalStatus::NotMet
+        }
+    }
+}
+
+/// A study service shown on the dashboard. Implementors are the rendered
+/// data for the service, combining one or more `Cacheable` sources
+pub trait StudyService: Template + serde::Serialize + Send + Sized + 'static {
+    /// Used for the service's routes and the css class of its card
+    const ID: &'static str;
+    const DISPLAY_NAME: &'static str;
+
+    /// The cached data the service is built from
+    fn sources() -> Vec<CacheSource>;
+
+    /// Routes for views beyond the service's card
+    fn extra_routes(router: Router<AppState>) -> Router<AppState> {
+        router
+    }
+
+    fn fetch(
+        state: &AppState,
+    ) -> impl Future<Output = anyhow::Result<(Self, Option<DateTime<Utc>>)>> + Send;
+
+    fn study_counts(&self) -> StudyCounts;
+
+    fn goal_status(&self) -> GoalStatus;
+}
+
+pub async fn html_handler<S: StudyService>(
+    State(state): State<AppState>,
+) -> Result<(HeaderMap, Html<String>), HtmlErrorResponse> {
+    let (data, expires_at) = S::fetch(&state).await.map_err(internal_error)?;
+
+    let headers = add_expiry_header(HeaderMap::new(), &[expires_at]);
+    let html_string = data.render().map_err(internal_error)?;
+
+    Ok((headers, Html(html_string)))
+}
+
+pub async fn json_handler<S: StudyService>(
+    State(state): State<AppState>,
+) -> Result<(HeaderMap, Json<ApiResponse<S>>), JsonErrorResponse> {
+    let (data, expires_at) = S::fetch(&state).await.map_err(json_internal_error)?;
+
+    let headers = add_expiry_header(HeaderMap::new(), &[expires_at]);
+
+    Ok((headers, Json(ApiResponse { data, expires_at })))
+}
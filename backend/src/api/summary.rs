@@ -0,0 +1,299 @@
+use askama::Template;
+use axum::{extract::State, http::HeaderMap, response::Html, Json};
+use chrono::{DateTime, Utc};
+
+use crate::{
+    api::{
+        add_expiry_header, earliest_expiry, internal_error,
+        study_service::{GoalStatus, StudyCounts},
+        user_error_message, ApiResponse, HtmlErrorResponse,
+    },
+    state::AppState,
+};
+
+#[derive(serde::Serialize)]
+pub struct ServiceSummary {
+    service: &'static str,
+    #[serde(flatten)]
+    status: ServiceStatus,
+}
+
+#[derive(serde::Serialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum ServiceStatus {
+    Ok {
+        #[serde(flatten)]
+        counts: StudyCounts,
+        goal_status: GoalStatus,
+        expires_at: Option<DateTime<Utc>>,
+    },
+    Error {
+        error: String,
+    },
+}
+
+#[derive(serde::Serialize, Template)]
+#[template(path = "summary.html")]
+pub struct SummaryData {
+    total_review_count: u32,
+    total_new_item_count: u32,
+    goals_met_count: u32,
+    goals_tracked_count: u32,
+    services: Vec<ServiceSummary>,
+}
+
+impl SummaryData {
+    pub fn new(services: Vec<ServiceSummary>) -> Self {
+        let counts: Vec<&StudyCounts> = services.iter().filter_map(|s| s.counts()).collect();
+        let goals_met_count = services.iter().filter(|s| s.goal_met()).count();
+        let goals_tracked_count = services.iter().filter(|s| !s.goal_suspended()).count();
+
+        Self {
+            total_review_count: counts.iter().map(|c| c.active_review_count).sum(),
+            total_new_item_count: counts.iter().map(|c| c.new_item_count).sum(),
+            goals_met_count: goals_met_count as u32,
+            goals_tracked_count: goals_tracked_count as u32,
+            services,
+        }
+    }
+
+    fn expires_at(&self) -> Option<DateTime<Utc>> {
+        let expiry_times: Vec<Option<DateTime<Utc>>> = self
+            .services
+            .iter()
+            .map(|service| match service.status {
+                ServiceStatus::Ok { expires_at, .. } => expires_at,
+                ServiceStatus::Error { .. } => None,
+            })
+            .collect();
+
+        earliest_expiry(&expiry_times)
+    }
+}
+
+impl ServiceSummary {
+    pub fn from_result(
+        service: &'static str,
+        result: anyhow::Result<(StudyCounts, GoalStatus, Option<DateTime<Utc>>)>,
+    ) -> Self {
+        let status = match result {
+            Ok((counts, goal_status, expires_at)) => ServiceStatus::Ok {
+                counts,
+                goal_status,
+                expires_at,
+            },
+            Err(err) => {
+                tracing::error!("Error fetching {} summary: {:#}", service, err);
+
+                ServiceStatus::Error {
+                    error: user_error_message(&err),
+                }
+            }
+        };
+
+        Self { service, status }
+    }
+
+    fn counts(&self) -> Option<&StudyCounts> {
+        match &self.status {
+            ServiceStatus::Ok { counts, .. } => Some(counts),
+            ServiceStatus::Error { .. } => None,
+        }
+    }
+
+    fn goal_met(&self) -> bool {
+        matches!(
+            self.status,
+            ServiceStatus::Ok {
+                goal_status: GoalStatus::Met,
+                ..
+            }
+        )
+    }
+
+    fn goal_suspended(&self) -> bool {
+        matches!(
+            self.status,
+            ServiceStatus::Ok {
+                goal_status: GoalStatus::Suspended,
+                ..
+            }
+        )
+    }
+
+    fn error(&self) -> Option<&str> {
+        match &self.status {
+            ServiceStatus::Ok { .. } => None,
+            ServiceStatus::Error { error } => Some(error),
+        }
+    }
+}
+
+pub async fn summary_handler(
+    State(state): State<AppState>,
+) -> Result<(HeaderMap, Html<String>), HtmlErrorResponse> {
+    let summary = get_summary_data(&state).await;
+
+    let headers = add_expiry_header(HeaderMap::new(), &[summary.expires_at()]);
+    let html_string = summary.render().map_err(internal_error)?;
+
+    Ok((headers, Html(html_string)))
+}
+
+pub async fn summary_json_handler(
+    State(state): State<AppState>,
+) -> (HeaderMap, Json<ApiResponse<SummaryData>>) {
+    let summary = get_summary_data(&state).await;
+
+    let expires_at = summary.expires_at();
+    let headers = add_expiry_header(HeaderMap::new(), &[expires_at]);
+
+    (
+        headers,
+        Json(ApiResponse {
+            data: summary,
+            expires_at,
+        }),
+    )
+}
+
+async fn get_summary_data(state: &AppState) -> SummaryData {
+    // spawn every service up front so they are fetched concurrently
+    let pending: Vec<_> = state
+        .registry
+        .services()
+        .iter()
+        .map(|service| (service.id, tokio::spawn(service.summarize(state.clone()))))
+        .collect();
+
+    let mut services = Vec::with_capacity(pending.len());
+
+    for (service_id, handle) in pending {
+        let summary = handle
+            .await
+            .unwrap_or_else(|err| ServiceSummary::from_result(service_id, Err(err.into())));
+
+        services.push(summary);
+    }
+
+    SummaryData::new(services)
+}
+
+#[cfg(test)]
+mod test_super {
+    use anyhow::anyhow;
+
+    use super::*;
+    use crate::api::SessionExpired;
+
+    fn counts(
+        reviews: u32,
+        new_items: u32,
+        goal_met: bool,
+    ) -> (StudyCounts, GoalStatus, Option<DateTime<Utc>>) {
+        let counts = StudyCounts {
+            active_review_count: reviews,
+            new_item_count: new_items,
+        };
+
+        (counts, GoalStatus::from(goal_met), None)
+    }
+
+    #[test]
+    fn test_summary_totals_skip_failed_services() {
+        let summary = SummaryData::new(vec![
+            ServiceSummary::from_result("wanikani", Ok(counts(10, 5, true))),
+            ServiceSummary::from_result("bunpro", Ok(counts(3, 0, false))),
+            ServiceSummary::from_result("satori", Err(anyhow!("cookie expired"))),
+            ServiceSummary::from_result("anki", Ok(counts(7, 20, true))),
+        ]);
+
+        assert_eq!(summary.total_review_count, 20);
+        assert_eq!(summary.total_new_item_count, 25);
+        assert_eq!(summary.goals_met_count, 2);
+    }
+
+    #[test]
+    fn test_suspended_goals_are_not_tracked() {
+        let (wanikani_counts, _, _) = counts(0, 0, false);
+
+        let summary = SummaryData::new(vec![
+            ServiceSummary::from_result(
+                "wanikani",
+                Ok((wanikani_counts, GoalStatus::Suspended, None)),
+            ),
+            ServiceSummary::from_result("anki", Ok(counts(7, 20, true))),
+        ]);
+
+        assert_eq!(summary.goals_met_count, 1);
+        assert_eq!(summary.goals_tracked_count, 1);
+    }
+
+    #[test]
+    fn test_summary_serializes_per_service_errors() {
+        let summary = SummaryData::new(vec![ServiceSummary::from_result(
+            "satori",
+            Err(anyhow!("cookie expired")),
+        )]);
+
+        let json = serde_json::to_value(&summary).unwrap();
+
+        assert_eq!(json["services"][0]["service"], "satori");
+        assert_eq!(json["services"][0]["status"], "error");
+        assert_eq!(json["services"][0]["error"], "Something went wrong");
+    }
+
+    #[test]
+    fn test_summary_errors_only_show_typed_messages() {
+        let summary = SummaryData::new(vec![
+            ServiceSummary::from_result(
+                "bunpro",
+                Err(anyhow!(
+                    "error sending request for url (https://bunpro.jp/api/user/secret-token/study_queue)"
+                )),
+            ),
+            ServiceSummary::from_result(
+                "satori",
+                Err(SessionExpired { service: "Satori" }.into()),
+            ),
+        ]);
+
+        let json = serde_json::to_value(&summary).unwrap();
+
+        assert_eq!(json["services"][0]["error"], "Something went wrong");
+        assert_eq!(json["services"][1]["error"], "Satori session expired");
+    }
+
+    #[test]
+    fn test_summary_serializes_goal_status() {
+        let summary = SummaryData::new(vec![ServiceSummary::from_result(
+            "anki",
+            Ok(counts(7, 20, true)),
+        )]);
+
+        let json = serde_json::to_value(&summary).unwrap();
+
+        assert_eq!(json["services"][0]["status"], "ok");
+        assert_eq!(json["services"][0]["active_review_count"], 7);
+        assert_eq!(json["services"][0]["goal_status"], "met");
+    }
+
+    #[test]
+    fn test_summary_expiry_uses_nearest_successful_service() {
+        let early = DateTime::parse_from_rfc3339("2024-06-21T10:00:00Z")
+            .unwrap()
+            .with_timezone(&Utc);
+        let late = DateTime::parse_from_rfc3339("2024-06-21T11:00:00Z")
+            .unwrap()
+            .with_timezone(&Utc);
+        let counts = counts(1, 0, false);
+
+        let summary = SummaryData::new(vec![
+            ServiceSummary::from_result("wanikani", Ok((counts.0, counts.1, Some(late)))),
+            ServiceSummary::from_result("anki", Ok((counts.0, counts.1, Some(early)))),
+            ServiceSummary::from_result("satori", Err(anyhow!("cookie expired"))),
+        ]);
+
+        assert_eq!(summary.expires_at(), Some(early));
+    }
+}
@@ -1,4 +1,3 @@
+pub mod collection;
 pub mod data;
 pub mod request;
-
-pub use request::wanikani_handler;
@@ -0,0 +1,266 @@
+use std::{collections::BTreeMap, time::Duration as StdDuration};
+
+use chrono::{DateTime, SecondsFormat, Utc};
+use reqwest::{header::HeaderMap, Client, StatusCode};
+use serde::de::DeserializeOwned;
+
+/// Times a rate limited request is retried before giving up
+const MAX_RATE_LIMIT_RETRIES: u32 = 3;
+/// WaniKani's limit resets every minute, so never wait longer than that
+const MAX_RATE_LIMIT_WAIT_SECONDS: i64 = 60;
+
+/// A page of a WaniKani collection endpoint. Collections are paged at up to
+/// 500 items, with `next_url` set while there are more
+#[derive(serde::Deserialize)]
+struct CollectionPage<T> {
+    pages: Pages,
+    total_count: u32,
+    data_updated_at: Option<DateTime<Utc>>,
+    data: Vec<Resource<T>>,
+}
+
+#[derive(serde::Deserialize)]
+struct Pages {
+    next_url: Option<String>,
+}
+
+#[derive(serde::Serialize, serde::Deserialize)]
+pub struct Resource<T> {
+    pub id: u32,
+    pub data_updated_at: DateTime<Utc>,
+    pub data: T,
+}
+
+/// Fetches every page of the collection at `url`, returning each item's data
+pub async fn fetch_all<T: DeserializeOwned>(client: &Client, url: &str) -> anyhow::Result<Vec<T>> {
+    let resources = fetch_all_resources(client, url).await?;
+
+    Ok(resources
+        .into_iter()
+        .map(|resource| resource.data)
+        .collect())
+}
+
+/// Fetches every page of the collection at `url`, keeping each item's id
+pub async fn fetch_all_resources<T: DeserializeOwned>(
+    client: &Client,
+    url: &str,
+) -> anyhow::Result<Vec<Resource<T>>> {
+    let mut resources = Vec::new();
+    let mut next_url = Some(url.to_string());
+
+    while let Some(url) = next_url {
+        let page: CollectionPage<T> = fetch_page(client, &url).await?;
+
+        resources.extend(page.data);
+        next_url = page.pages.next_url;
+    }
+
+    Ok(resources)
+}
+
+/// Number of items in the collection at `url`, without paging through it
+pub async fn fetch_total_count(client: &Client, url: &str) -> anyhow::Result<u32> {
+    let page: CollectionPage<serde::de::IgnoredAny> = fetch_page(client, url).await?;
+
+    Ok(page.total_count)
+}
+
+/// A collection kept up to date with `updated_after`, so after the first
+/// sync only the items that changed since the last one are fetched
+#[derive(serde::Serialize, serde::Deserialize)]
+pub struct SyncedCollection<T> {
+    pub synced_at: Option<DateTime<Utc>>,
+    pub items: BTreeMap<u32, Resource<T>>,
+}
+
+impl<T> Default for SyncedCollection<T> {
+    fn default() -> Self {
+        Self {
+            synced_at: None,
+            items: BTreeMap::new(),
+        }
+    }
+}
+
+impl<T: DeserializeOwned> SyncedCollection<T> {
+    /// Fetches the items of the collection at `url` changed since the last
+    /// sync, replacing the previous versions
+    pub async fn sync(mut self, client: &Client, url: &str) -> anyhow::Result<Self> {
+        let mut next_url = Some(updated_after_url(url, self.synced_at));
+
+        while let Some(url) = next_url {
+            let page: CollectionPage<T> = fetch_page(client, &url).await?;
+
+            next_url = page.pages.next_url.clone();
+            self.merge(page);
+        }
+
+        Ok(self)
+    }
+
+    fn merge(&mut self, page: CollectionPage<T>) {
+        self.synced_at = self.synced_at.max(page.data_updated_at);
+
+        for resource in page.data {
+            self.items.insert(resource.id, resource);
+        }
+    }
+
+    /// Builds a collection from a single page of a response
+    pub fn try_from_response_body(response_body: &str) -> anyhow::Result<Self> {
+        let mut collection = Self::default();
+        collection.merge(serde_json::from_str(response_body)?);
+
+        Ok(collection)
+    }
+}
+
+fn updated_after_url(url: &str, updated_after: Option<DateTime<Utc>>) -> String {
+    let Some(updated_after) = updated_after else {
+        return url.to_string();
+    };
+
+    let separator = if url.contains('?') { '&' } else { '?' };
+
+    format!(
+        "{url}{separator}updated_after={}",
+        updated_after.to_rfc3339_opts(SecondsFormat::Millis, true)
+    )
+}
+
+async fn fetch_page<T: DeserializeOwned>(
+    client: &Client,
+    url: &str,
+) -> anyhow::Result<CollectionPage<T>> {
+    let mut retries = 0;
+
+    loop {
+        let response = client.get(url).send().await?;
+
+        if response.status() == StatusCode::TOO_MANY_REQUESTS && retries < MAX_RATE_LIMIT_RETRIES {
+            let wait = rate_limit_wait(response.headers(), Utc::now());
+            tracing::info!("WaniKani rate limit reached, retrying in {wait:?}");
+
+            tokio::time::sleep(wait).await;
+            retries += 1;
+            continue;
+        }
+
+        let body = response.error_for_status()?.text().await?;
+
+        return Ok(serde_json::from_str(&body)?);
+    }
+}
+
+/// Time until the rate limit resets, from the `RateLimit-Reset` header
+fn rate_limit_wait(headers: &HeaderMap, now: DateTime<Utc>) -> StdDuration {
+    let reset_at = headers
+        .get("RateLimit-Reset")
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse::<i64>().ok());
+
+    let wait_seconds = match reset_at {
+        // the reset is to the second, wait one more so it's definitely passed
+        Some(reset_at) => (reset_at - now.timestamp() + 1).clamp(1, MAX_RATE_LIMIT_WAIT_SECONDS),
+        None => MAX_RATE_LIMIT_WAIT_SECONDS,
+    };
+
+    StdDuration::from_secs(wait_seconds as u64)
+}
+
+#[cfg(test)]
+mod test_super {
+    use chrono::TimeZone;
+
+    use super::*;
+
+    #[derive(serde::Deserialize)]
+    struct LevelProgression {
+        level: u32,
+    }
+
+    #[test]
+    fn test_merge_replaces_updated_items() {
+        let mut collection: SyncedCollection<LevelProgression> =
+            SyncedCollection::try_from_response_body(include_str!(
+                "./fixtures/level_progressions.json"
+            ))
+            .unwrap();
+
+        assert_eq!(collection.items.len(), 3);
+        assert_eq!(
+            collection.synced_at,
+            Some("2023-07-10T09:12:40.016745Z".parse().unwrap())
+        );
+
+        let update = r#"{
+            "pages": { "next_url": null },
+            "total_count": 1,
+            "data_updated_at": "2023-07-20T01:00:00.000000Z",
+            "data": [
+                { "id": 2198113, "data_updated_at": "2023-07-20T01:00:00.000000Z", "data": { "level": 13 } }
+            ]
+        }"#;
+        collection.merge(serde_json::from_str(update).unwrap());
+
+        assert_eq!(collection.items.len(), 3);
+        assert_eq!(collection.items[&2198113].data.level, 13);
+        assert_eq!(
+            collection.synced_at,
+            Some("2023-07-20T01:00:00Z".parse().unwrap())
+        );
+    }
+
+    #[test]
+    fn test_empty_update_keeps_synced_at() {
+        let mut collection: SyncedCollection<LevelProgression> =
+            SyncedCollection::try_from_response_body(include_str!(
+                "./fixtures/level_progressions.json"
+            ))
+            .unwrap();
+
+        let update = r#"{ "pages": { "next_url": null }, "total_count": 0, "data_updated_at": null, "data": [] }"#;
+        collection.merge(serde_json::from_str(update).unwrap());
+
+        assert_eq!(
+            collection.synced_at,
+            Some("2023-07-10T09:12:40.016745Z".parse().unwrap())
+        );
+    }
+
+    #[test]
+    fn test_updated_after_url() {
+        let updated_after = Utc.with_ymd_and_hms(2023, 7, 15, 15, 0, 0).unwrap();
+
+        assert_eq!(
+            updated_after_url("https://api.wanikani.com/v2/subjects", Some(updated_after)),
+            "https://api.wanikani.com/v2/subjects?updated_after=2023-07-15T15:00:00.000Z"
+        );
+        assert_eq!(
+            updated_after_url(
+                "https://api.wanikani.com/v2/assignments?hidden=false",
+                Some(updated_after)
+            ),
+            "https://api.wanikani.com/v2/assignments?hidden=false&updated_after=2023-07-15T15:00:00.000Z"
+        );
+        assert_eq!(
+            updated_after_url("https://api.wanikani.com/v2/subjects", None),
+            "https://api.wanikani.com/v2/subjects"
+        );
+    }
+
+    #[test]
+    fn test_rate_limit_wait_until_reset() {
+        let now = Utc.with_ymd_and_hms(2023, 7, 15, 15, 0, 30).unwrap();
+
+        let mut headers = HeaderMap::new();
+        headers.insert("RateLimit-Reset", "1689433260".parse().unwrap());
+        assert_eq!(rate_limit_wait(&headers, now), StdDuration::from_secs(31));
+
+        assert_eq!(
+            rate_limit_wait(&HeaderMap::new(), now),
+            StdDuration::from_secs(60)
+        );
+    }
+}
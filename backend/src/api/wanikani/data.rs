@@ -1,6 +1,16 @@
+use std::collections::BTreeMap;
+
 use askama::Template;
 use chrono::{DateTime, Utc};
 
+use crate::{
+    api::{
+        forecast::{hourly_forecast, ForecastChart, ForecastHour},
+        wanikani::collection::{Resource, SyncedCollection},
+    },
+    config::WanikaniDailyGoal,
+};
+
 #[derive(serde::Deserialize, serde::Serialize)]
 pub struct WanikaniSummaryResponse {
     data_updated_at: DateTime<Utc>,
@@ -13,11 +23,16 @@ impl WanikaniSummaryResponse {
 
         Ok(json_data)
     }
+
+    pub fn next_unlock_at(&self, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        self.data.next_unlock_at(now)
+    }
 }
 
 #[derive(serde::Deserialize, serde::Serialize)]
 pub struct SummaryDataStructure {
     lessons: Vec<Lesson>,
+    next_reviews_at: Option<DateTime<Utc>>,
     reviews: Vec<Review>,
 }
 
@@ -36,10 +51,38 @@ impl SummaryDataStructure {
             None => 0, // no reviews I guess - yay!
         }
     }
+
+    /// When the next batch of reviews unlocks. Reviews are grouped into
+    /// hourly buckets, empty buckets don't change the review count
+    fn next_unlock_at(&self, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        self.reviews
+            .iter()
+            .find(|review| review.available_at > now && !review.subject_ids.is_empty())
+            .map(|review| review.available_at)
+            .or(self
+                .next_reviews_at
+                .filter(|next_reviews_at| *next_reviews_at > now))
+    }
+
+    /// The first bucket is the reviews available now, the rest are the
+    /// reviews unlocking in each following hour
+    fn review_forecast(&self) -> Vec<ForecastHour> {
+        let Some(current) = self.reviews.first() else {
+            return Vec::new();
+        };
+
+        let hours = self
+            .reviews
+            .iter()
+            .map(|review| (review.available_at, review.total_count()));
+
+        hourly_forecast(current.available_at, self.current_reviews(), hours)
+    }
 }
 
 #[derive(serde::Deserialize, serde::Serialize)]
 struct Lesson {
+    available_at: DateTime<Utc>,
     subject_ids: Vec<u32>,
 }
 
@@ -51,20 +94,203 @@ impl Lesson {
 
 type Review = Lesson;
 
+#[derive(serde::Serialize, serde::Deserialize)]
+pub struct ReviewStatistic {
+    subject_id: u32,
+    subject_type: SubjectType,
+    meaning_correct: u32,
+    meaning_incorrect: u32,
+    meaning_current_streak: u32,
+    reading_correct: u32,
+    reading_incorrect: u32,
+    reading_current_streak: u32,
+    hidden: bool,
+}
+
+impl ReviewStatistic {
+    fn incorrect_count(&self) -> u32 {
+        self.meaning_incorrect + self.reading_incorrect
+    }
+
+    fn incorrect_ratio(&self) -> f64 {
+        let total = self.meaning_correct + self.reading_correct + self.incorrect_count();
+
+        if total == 0 {
+            return 0.0;
+        }
+
+        f64::from(self.incorrect_count()) / f64::from(total)
+    }
+
+    /// Radicals have no reading, so their reading streak is always 1
+    fn current_streak(&self) -> u32 {
+        match self.subject_type {
+            SubjectType::Radical => self.meaning_current_streak,
+            _ => self.meaning_current_streak.min(self.reading_current_streak),
+        }
+    }
+}
+
+/// Subjects reviewed during the JST day beginning at `day_start`. A review
+/// statistic is updated whenever its subject is reviewed, so this is the
+/// number of statistics updated since then
 #[derive(serde::Serialize, serde::Deserialize)]
 pub struct WanikaniReviewStats {
-    total_count: u32,
+    pub day_start: DateTime<Utc>,
+    pub subjects_reviewed: u32,
 }
 
 impl WanikaniReviewStats {
+    /// Yesterday's count is left in the cache after midnight until it's refreshed
+    fn reviewed_since(&self, today_start: DateTime<Utc>) -> u32 {
+        if self.day_start == today_start {
+            self.subjects_reviewed
+        } else {
+            0
+        }
+    }
+}
+
+/// Every review statistic, synced incrementally for the leech report
+#[derive(serde::Serialize, serde::Deserialize, Default)]
+pub struct WanikaniReviewStatistics {
+    pub statistics: SyncedCollection<ReviewStatistic>,
+}
+
+impl WanikaniReviewStatistics {
+    pub fn try_from_response_body(response_body: &str) -> anyhow::Result<Self> {
+        Ok(Self {
+            statistics: SyncedCollection::try_from_response_body(response_body)?,
+        })
+    }
+}
+
+/// Lessons started during the JST day beginning at `day_start`
+#[derive(serde::Serialize, serde::Deserialize)]
+pub struct WanikaniLessonStats {
+    pub day_start: DateTime<Utc>,
+    pub lessons_started: u32,
+}
+
+impl WanikaniLessonStats {
+    pub fn new(day_start: DateTime<Utc>, assignments: &[Assignment]) -> Self {
+        let lessons_started = assignments
+            .iter()
+            .filter(|assignment| assignment.started_at.is_some_and(|at| at >= day_start))
+            .count() as u32;
+
+        Self {
+            day_start,
+            lessons_started,
+        }
+    }
+
+    /// Yesterday's count is left in the cache after midnight until it's refreshed
+    fn lessons_started_since(&self, today_start: DateTime<Utc>) -> u32 {
+        if self.day_start == today_start {
+            self.lessons_started
+        } else {
+            0
+        }
+    }
+}
+
+/// How far through the configured daily goal today's studying is
+#[derive(serde::Serialize, Debug, PartialEq)]
+pub struct DailyGoalProgress {
+    pub goal: WanikaniDailyGoal,
+    pub completed: u32,
+    pub target: u32,
+    pub met: bool,
+}
+
+impl DailyGoalProgress {
+    pub fn new(
+        goal: WanikaniDailyGoal,
+        reviews_done: u32,
+        lessons_done: u32,
+        reviews_remaining: u32,
+    ) -> Self {
+        let (completed, target) = match goal {
+            WanikaniDailyGoal::Lessons { count } => (lessons_done, count),
+            WanikaniDailyGoal::QueueCleared => (reviews_done, reviews_done + reviews_remaining),
+        };
+
+        let met = match goal {
+            WanikaniDailyGoal::Lessons { .. } => completed >= target,
+            WanikaniDailyGoal::QueueCleared => reviews_remaining == 0,
+        };
+
+        Self {
+            goal,
+            completed,
+            target,
+            met,
+        }
+    }
+
+    fn unit(&self) -> &'static str {
+        match self.goal {
+            WanikaniDailyGoal::Lessons { .. } => "lessons",
+            WanikaniDailyGoal::QueueCleared => "items reviewed",
+        }
+    }
+}
+
+#[derive(serde::Deserialize, serde::Serialize)]
+pub struct WanikaniUser {
+    data: UserData,
+}
+
+#[derive(serde::Deserialize, serde::Serialize, Clone, Debug, PartialEq)]
+pub struct UserData {
+    pub level: u32,
+    pub subscription: Subscription,
+    pub current_vacation_started_at: Option<DateTime<Utc>>,
+}
+
+#[derive(serde::Deserialize, serde::Serialize, Clone, Debug, PartialEq)]
+pub struct Subscription {
+    pub active: bool,
+    #[serde(rename = "type")]
+    pub subscription_type: SubscriptionType,
+    pub max_level_granted: u32,
+    pub period_ends_at: Option<DateTime<Utc>>,
+}
+
+#[derive(serde::Deserialize, serde::Serialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum SubscriptionType {
+    Free,
+    Recurring,
+    Lifetime,
+    /// WaniKani's placeholder for users whose subscription state is unknown
+    Unknown,
+}
+
+impl WanikaniUser {
     pub fn try_from_response_body(response_body: &str) -> anyhow::Result<Self> {
         let json_data = serde_json::from_str(response_body)?;
 
         Ok(json_data)
     }
+}
+
+impl UserData {
+    pub fn on_vacation(&self) -> bool {
+        self.current_vacation_started_at.is_some()
+    }
 
-    fn daily_study_goal_met(&self) -> bool {
-        self.total_count > 0
+    /// The user is above the levels their subscription grants, so has
+    /// nothing to review until it's renewed
+    pub fn subscription_lapsed(&self) -> bool {
+        self.level > self.subscription.max_level_granted
+    }
+
+    /// Reviews stop while on vacation or locked out of levels, so missing the
+    /// goal then isn't a missed day
+    pub fn goal_suspended(&self) -> bool {
+        self.on_vacation() || self.subscription_lapsed()
     }
 }
 
@@ -72,20 +298,331 @@ impl WanikaniReviewStats {
 #[template(path = "wanikani.html")]
 pub struct WanikaniData {
     data_updated_at: DateTime<Utc>,
-    active_lesson_count: u32,
-    active_review_count: u32,
-    daily_study_goal_met: bool,
+    pub active_lesson_count: u32,
+    pub active_review_count: u32,
+    pub daily_study_goal_met: bool,
+    pub daily_goal: DailyGoalProgress,
+    pub user: UserData,
+    pub review_forecast: Vec<ForecastHour>,
+    pub stale_since: Option<DateTime<Utc>>,
 }
 
 impl WanikaniData {
-    pub fn new(summary: WanikaniSummaryResponse, review_stats: WanikaniReviewStats) -> Self {
+    pub fn new(
+        summary: WanikaniSummaryResponse,
+        review_stats: WanikaniReviewStats,
+        lesson_stats: WanikaniLessonStats,
+        user: WanikaniUser,
+        daily_goal: WanikaniDailyGoal,
+        today_start: DateTime<Utc>,
+    ) -> Self {
+        let active_review_count = summary.data.current_reviews();
+        let daily_goal = DailyGoalProgress::new(
+            daily_goal,
+            review_stats.reviewed_since(today_start),
+            lesson_stats.lessons_started_since(today_start),
+            active_review_count,
+        );
+
         WanikaniData {
             data_updated_at: summary.data_updated_at,
             active_lesson_count: summary.data.total_lessons(),
-            active_review_count: summary.data.current_reviews(),
-            daily_study_goal_met: review_stats.daily_study_goal_met(),
+            active_review_count,
+            daily_study_goal_met: daily_goal.met && !user.data.goal_suspended(),
+            daily_goal,
+            user: user.data,
+            review_forecast: summary.data.review_forecast(),
+            stale_since: None,
         }
     }
+
+    fn forecast_chart(&self) -> ForecastChart {
+        ForecastChart::new(&self.review_forecast)
+    }
+}
+
+/// Share of a level's kanji that need passing to unlock the next level
+const KANJI_PASS_PERCENTAGE: u32 = 90;
+
+#[derive(serde::Deserialize)]
+pub struct LevelProgression {
+    level: u32,
+    unlocked_at: Option<DateTime<Utc>>,
+    abandoned_at: Option<DateTime<Utc>>,
+}
+
+/// The level being worked on. Progressions abandoned by a reset are skipped
+pub fn current_level(progressions: &[LevelProgression]) -> Option<&LevelProgression> {
+    progressions
+        .iter()
+        .filter(|progression| progression.abandoned_at.is_none())
+        .max_by_key(|progression| progression.level)
+}
+
+impl LevelProgression {
+    pub fn level(&self) -> u32 {
+        self.level
+    }
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum SubjectType {
+    Radical,
+    Kanji,
+    Vocabulary,
+    KanaVocabulary,
+}
+
+#[derive(serde::Serialize, serde::Deserialize)]
+pub struct Assignment {
+    subject_type: SubjectType,
+    srs_stage: u8,
+    started_at: Option<DateTime<Utc>>,
+    passed_at: Option<DateTime<Utc>>,
+    hidden: bool,
+}
+
+/// Every started assignment, synced incrementally for the SRS stage counts
+#[derive(serde::Serialize, serde::Deserialize, Default)]
+pub struct WanikaniAssignments {
+    pub assignments: SyncedCollection<Assignment>,
+}
+
+/// Started items grouped by SRS stage, lessons (stage 0) aren't counted
+#[derive(serde::Serialize, serde::Deserialize, Default, Debug, PartialEq)]
+pub struct SrsStageCounts {
+    pub apprentice: u32,
+    pub guru: u32,
+    pub master: u32,
+    pub enlightened: u32,
+    pub burned: u32,
+}
+
+impl SrsStageCounts {
+    pub fn new(assignments: &[Assignment]) -> Self {
+        let mut counts = Self::default();
+
+        for assignment in assignments.iter().filter(|assignment| !assignment.hidden) {
+            match assignment.srs_stage {
+                1..=4 => counts.apprentice += 1,
+                5..=6 => counts.guru += 1,
+                7 => counts.master += 1,
+                8 => counts.enlightened += 1,
+                9 => counts.burned += 1,
+                _ => {}
+            }
+        }
+
+        counts
+    }
+}
+
+/// Items of one type on the current level that have reached Guru, against
+/// how many are needed to level up
+#[derive(serde::Serialize, serde::Deserialize, Debug, PartialEq)]
+pub struct LevelItemProgress {
+    pub passed: u32,
+    pub required: u32,
+    pub total: u32,
+}
+
+impl LevelItemProgress {
+    fn new(assignments: &[Assignment], subject_type: SubjectType, total: u32) -> Self {
+        let passed = assignments
+            .iter()
+            .filter(|a| a.subject_type == subject_type && a.passed_at.is_some())
+            .count() as u32;
+
+        // radicals aren't counted for levelling up, but unlock the kanji
+        let required = match subject_type {
+            SubjectType::Kanji => (total * KANJI_PASS_PERCENTAGE).div_ceil(100),
+            _ => total,
+        };
+
+        Self {
+            passed,
+            required,
+            total,
+        }
+    }
+}
+
+/// Radicals and kanji of each level, used to work out the level up progress
+pub struct LevelSubjectCounts {
+    pub radicals: u32,
+    pub kanji: u32,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Template)]
+#[template(path = "wanikani_progress.html")]
+pub struct WanikaniLevelProgress {
+    pub level: u32,
+    pub level_unlocked_at: Option<DateTime<Utc>>,
+    pub radicals: LevelItemProgress,
+    pub kanji: LevelItemProgress,
+    pub srs_stages: SrsStageCounts,
+    #[serde(skip_deserializing)]
+    pub stale_since: Option<DateTime<Utc>>,
+}
+
+impl WanikaniLevelProgress {
+    pub fn new(
+        current_level: &LevelProgression,
+        level_assignments: &[Assignment],
+        level_subject_counts: LevelSubjectCounts,
+        started_assignments: &[Assignment],
+    ) -> Self {
+        Self {
+            level: current_level.level,
+            level_unlocked_at: current_level.unlocked_at,
+            radicals: LevelItemProgress::new(
+                level_assignments,
+                SubjectType::Radical,
+                level_subject_counts.radicals,
+            ),
+            kanji: LevelItemProgress::new(
+                level_assignments,
+                SubjectType::Kanji,
+                level_subject_counts.kanji,
+            ),
+            srs_stages: SrsStageCounts::new(started_assignments),
+            stale_since: None,
+        }
+    }
+
+    fn days_on_level(&self) -> Option<i64> {
+        self.level_unlocked_at
+            .map(|unlocked_at| (Utc::now() - unlocked_at).num_days())
+    }
+}
+
+/// Subjects with fewer wrong answers aren't counted as leeches
+const MIN_LEECH_INCORRECT_COUNT: u32 = 3;
+const MAX_LEECHES: usize = 20;
+
+#[derive(serde::Serialize, serde::Deserialize)]
+struct Meaning {
+    meaning: String,
+    primary: bool,
+}
+
+#[derive(serde::Serialize, serde::Deserialize)]
+pub struct Subject {
+    level: u32,
+    /// Missing for radicals that are only shown as an image
+    characters: Option<String>,
+    meanings: Vec<Meaning>,
+}
+
+impl Subject {
+    fn primary_meaning(&self) -> Option<&str> {
+        self.meanings
+            .iter()
+            .find(|meaning| meaning.primary)
+            .map(|meaning| meaning.meaning.as_str())
+    }
+}
+
+/// The subjects fetched by id for the leeches, keyed by subject id
+#[derive(Default)]
+pub struct WanikaniSubjects {
+    pub subjects: BTreeMap<u32, Subject>,
+}
+
+impl WanikaniSubjects {
+    pub fn new(resources: Vec<Resource<Subject>>) -> Self {
+        Self {
+            subjects: resources
+                .into_iter()
+                .map(|resource| (resource.id, resource.data))
+                .collect(),
+        }
+    }
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Debug, PartialEq)]
+pub struct Leech {
+    pub subject_id: u32,
+    pub subject_type: SubjectType,
+    pub level: Option<u32>,
+    pub characters: Option<String>,
+    pub meaning: Option<String>,
+    pub incorrect_count: u32,
+    pub incorrect_percentage: u32,
+    pub current_streak: u32,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Template)]
+#[template(path = "wanikani_leeches.html")]
+pub struct WanikaniLeeches {
+    pub leeches: Vec<Leech>,
+    #[serde(skip_deserializing)]
+    pub stale_since: Option<DateTime<Utc>>,
+}
+
+impl WanikaniLeeches {
+    /// Ids of the subjects that make the leech list, to only fetch those
+    pub fn subject_ids(review_stats: &WanikaniReviewStatistics) -> Vec<u32> {
+        ranked_leeches(review_stats)
+            .iter()
+            .map(|statistic| statistic.subject_id)
+            .collect()
+    }
+
+    pub fn new(review_stats: &WanikaniReviewStatistics, subjects: &WanikaniSubjects) -> Self {
+        let leeches = ranked_leeches(review_stats)
+            .into_iter()
+            .map(|statistic| {
+                let subject = subjects.subjects.get(&statistic.subject_id);
+
+                Leech {
+                    subject_id: statistic.subject_id,
+                    subject_type: statistic.subject_type,
+                    level: subject.map(|subject| subject.level),
+                    characters: subject.and_then(|subject| subject.characters.clone()),
+                    meaning: subject
+                        .and_then(|subject| subject.primary_meaning())
+                        .map(String::from),
+                    incorrect_count: statistic.incorrect_count(),
+                    incorrect_percentage: (statistic.incorrect_ratio() * 100.0).round() as u32,
+                    current_streak: statistic.current_streak(),
+                }
+            })
+            .collect();
+
+        Self {
+            leeches,
+            stale_since: None,
+        }
+    }
+}
+
+/// Ranks subjects by how often they're answered wrong, divided by the
+/// current streak so items that have since stuck drop down the list
+fn ranked_leeches(review_stats: &WanikaniReviewStatistics) -> Vec<&ReviewStatistic> {
+    let mut candidates: Vec<(f64, &Resource<ReviewStatistic>)> = review_stats
+        .statistics
+        .items
+        .values()
+        .filter(|statistic| {
+            !statistic.data.hidden && statistic.data.incorrect_count() >= MIN_LEECH_INCORRECT_COUNT
+        })
+        .map(|statistic| {
+            let streak = statistic.data.current_streak().max(1);
+            let score = statistic.data.incorrect_ratio() / f64::from(streak);
+
+            (score, statistic)
+        })
+        .collect();
+
+    candidates.sort_by(|(a, _), (b, _)| b.total_cmp(a));
+
+    candidates
+        .into_iter()
+        .take(MAX_LEECHES)
+        .map(|(_, statistic)| &statistic.data)
+        .collect()
 }
 
 #[cfg(test)]
@@ -95,15 +632,102 @@ mod test_wanikani_stats {
     #[test]
     fn test_can_deserialize_stats() {
         let goal_met_response_data = include_str!("./fixtures/daily_goal_met.json");
-        let stats = WanikaniReviewStats::try_from_response_body(goal_met_response_data);
+        let stats = WanikaniReviewStatistics::try_from_response_body(goal_met_response_data);
 
-        assert!(stats.is_ok());
+        assert_eq!(stats.unwrap().statistics.items.len(), 5);
 
         let goal_not_met_response_data = include_str!("./fixtures/daily_goal_not_met.json");
-        let stats = WanikaniReviewStats::try_from_response_body(goal_not_met_response_data);
+        let stats = WanikaniReviewStatistics::try_from_response_body(goal_not_met_response_data);
 
         assert!(stats.is_ok());
     }
+
+    #[test]
+    fn test_reviewed_since() {
+        let stats = WanikaniReviewStats {
+            day_start: "2023-07-12T15:00:00Z".parse().unwrap(),
+            subjects_reviewed: 5,
+        };
+
+        assert_eq!(
+            stats.reviewed_since("2023-07-12T15:00:00Z".parse().unwrap()),
+            5
+        );
+        assert_eq!(
+            stats.reviewed_since("2023-07-13T15:00:00Z".parse().unwrap()),
+            0
+        );
+    }
+
+    #[test]
+    fn test_daily_goal_progress() {
+        let lessons = DailyGoalProgress::new(WanikaniDailyGoal::Lessons { count: 5 }, 42, 5, 10);
+        assert_eq!(
+            (lessons.completed, lessons.target, lessons.met),
+            (5, 5, true)
+        );
+        assert_eq!(lessons.unit(), "lessons");
+
+        let queue = DailyGoalProgress::new(WanikaniDailyGoal::QueueCleared, 42, 5, 10);
+        assert_eq!((queue.completed, queue.target, queue.met), (42, 52, false));
+        assert_eq!(queue.unit(), "items reviewed");
+
+        let cleared = DailyGoalProgress::new(WanikaniDailyGoal::QueueCleared, 0, 0, 0);
+        assert!(cleared.met);
+    }
+}
+
+#[cfg(test)]
+mod test_wanikani_user {
+    use super::*;
+
+    #[test]
+    fn test_active_subscription_tracks_goal() {
+        let user = WanikaniUser::try_from_response_body(include_str!("./fixtures/user.json"))
+            .unwrap()
+            .data;
+
+        assert_eq!(user.level, 12);
+        assert_eq!(
+            user.subscription.subscription_type,
+            SubscriptionType::Recurring
+        );
+        assert!(!user.goal_suspended());
+    }
+
+    #[test]
+    fn test_vacation_and_lapsed_subscription_suspend_goal() {
+        let user =
+            WanikaniUser::try_from_response_body(include_str!("./fixtures/user_on_vacation.json"))
+                .unwrap()
+                .data;
+
+        assert!(user.on_vacation());
+        assert!(user.subscription_lapsed());
+        assert!(user.goal_suspended());
+    }
+
+    #[test]
+    fn test_goal_not_met_while_suspended() {
+        let data = WanikaniData::new(
+            WanikaniSummaryResponse::try_from_response_body(include_str!(
+                "./fixtures/wanikani_with_no_reviews.json"
+            ))
+            .unwrap(),
+            WanikaniReviewStats {
+                day_start: Utc::now(),
+                subjects_reviewed: 0,
+            },
+            WanikaniLessonStats::new(Utc::now(), &[]),
+            WanikaniUser::try_from_response_body(include_str!("./fixtures/user_on_vacation.json"))
+                .unwrap(),
+            WanikaniDailyGoal::QueueCleared,
+            Utc::now(),
+        );
+
+        assert!(data.daily_goal.met);
+        assert!(!data.daily_study_goal_met);
+    }
 }
 
 #[cfg(test)]
@@ -127,4 +751,185 @@ mod test_wanikani_summary_response {
 
         assert!(response.is_ok());
     }
+
+    #[test]
+    fn test_next_unlock_skips_empty_hours() {
+        let response_data = include_str!("./fixtures/wanikani_with_reviews.json");
+        let response = WanikaniSummaryResponse::try_from_response_body(response_data).unwrap();
+
+        let now = "2023-06-23T13:04:42Z".parse().unwrap();
+
+        assert_eq!(
+            response.next_unlock_at(now),
+            Some("2023-06-23T15:00:00Z".parse().unwrap())
+        );
+    }
+
+    #[test]
+    fn test_review_forecast_uses_hourly_buckets() {
+        let response_data = include_str!("./fixtures/wanikani_with_reviews.json");
+        let response = WanikaniSummaryResponse::try_from_response_body(response_data).unwrap();
+
+        let forecast = response.data.review_forecast();
+
+        assert_eq!(forecast.len(), 24);
+        assert_eq!(forecast[0].review_count, 0);
+        assert_eq!(forecast[1].review_count, 15);
+        assert_eq!(forecast[1].cumulative_count, 95 + 15);
+        assert_eq!(forecast[23].cumulative_count, 95 + 69);
+    }
+
+    #[test]
+    fn test_next_unlock_with_no_current_reviews() {
+        let response_data = include_str!("./fixtures/wanikani_with_no_reviews.json");
+        let response = WanikaniSummaryResponse::try_from_response_body(response_data).unwrap();
+
+        let now = "2023-06-24T03:10:00Z".parse().unwrap();
+
+        assert_eq!(
+            response.next_unlock_at(now),
+            Some("2023-06-24T04:00:00Z".parse().unwrap())
+        );
+    }
+}
+
+#[cfg(test)]
+mod test_wanikani_level_progress {
+    use super::*;
+
+    fn fixture_data<T: serde::de::DeserializeOwned>(json: &str) -> Vec<T> {
+        let collection: SyncedCollection<T> =
+            SyncedCollection::try_from_response_body(json).unwrap();
+
+        collection
+            .items
+            .into_values()
+            .map(|resource| resource.data)
+            .collect()
+    }
+
+    #[test]
+    fn test_current_level_skips_abandoned_levels() {
+        let progressions: Vec<LevelProgression> =
+            fixture_data(include_str!("./fixtures/level_progressions.json"));
+
+        let current = current_level(&progressions).unwrap();
+
+        assert_eq!(current.level, 12);
+    }
+
+    #[test]
+    fn test_level_progress_counts_passed_items() {
+        let progressions: Vec<LevelProgression> =
+            fixture_data(include_str!("./fixtures/level_progressions.json"));
+        let assignments: Vec<Assignment> =
+            fixture_data(include_str!("./fixtures/level_assignments.json"));
+
+        let progress = WanikaniLevelProgress::new(
+            current_level(&progressions).unwrap(),
+            &assignments,
+            LevelSubjectCounts {
+                radicals: 4,
+                kanji: 31,
+            },
+            &assignments,
+        );
+
+        assert_eq!(
+            progress.radicals,
+            LevelItemProgress {
+                passed: 1,
+                required: 4,
+                total: 4
+            }
+        );
+        assert_eq!(
+            progress.kanji,
+            LevelItemProgress {
+                passed: 1,
+                required: 28,
+                total: 31
+            }
+        );
+        assert_eq!(
+            progress.srs_stages,
+            SrsStageCounts {
+                apprentice: 2,
+                guru: 2,
+                ..Default::default()
+            }
+        );
+    }
+
+    #[test]
+    fn test_srs_stage_counts_skip_hidden_assignments() {
+        let assignments: Vec<Assignment> = serde_json::from_str(
+            r#"[
+                { "subject_type": "kanji", "srs_stage": 5, "started_at": null, "passed_at": null, "hidden": false },
+                { "subject_type": "kanji", "srs_stage": 5, "started_at": null, "passed_at": null, "hidden": true }
+            ]"#,
+        )
+        .unwrap();
+
+        assert_eq!(SrsStageCounts::new(&assignments).guru, 1);
+    }
+
+    #[test]
+    fn test_lesson_stats_only_count_today() {
+        let assignments: Vec<Assignment> =
+            fixture_data(include_str!("./fixtures/level_assignments.json"));
+
+        let day_start = "2023-06-03T15:00:00Z".parse().unwrap();
+        let lesson_stats = WanikaniLessonStats::new(day_start, &assignments);
+
+        assert_eq!(lesson_stats.lessons_started_since(day_start), 2);
+        assert_eq!(
+            lesson_stats.lessons_started_since("2023-06-04T15:00:00Z".parse().unwrap()),
+            0
+        );
+    }
+}
+
+#[cfg(test)]
+mod test_wanikani_leeches {
+    use super::*;
+
+    #[test]
+    fn test_leeches_ranked_by_incorrect_ratio_and_streak() {
+        let review_stats = WanikaniReviewStatistics::try_from_response_body(include_str!(
+            "./fixtures/daily_goal_met.json"
+        ))
+        .unwrap();
+        let subjects: SyncedCollection<Subject> =
+            SyncedCollection::try_from_response_body(include_str!("./fixtures/subjects.json"))
+                .unwrap();
+        let subjects = WanikaniSubjects::new(subjects.items.into_values().collect());
+
+        assert_eq!(
+            WanikaniLeeches::subject_ids(&review_stats),
+            vec![7832, 6563, 1911]
+        );
+
+        let leeches = WanikaniLeeches::new(&review_stats, &subjects).leeches;
+
+        let ranked_ids: Vec<u32> = leeches.iter().map(|leech| leech.subject_id).collect();
+        assert_eq!(ranked_ids, vec![7832, 6563, 1911]);
+
+        assert_eq!(
+            leeches[1],
+            Leech {
+                subject_id: 6563,
+                subject_type: SubjectType::Vocabulary,
+                level: Some(22),
+                characters: Some("援助".to_string()),
+                meaning: Some("Assistance".to_string()),
+                incorrect_count: 6,
+                incorrect_percentage: 13,
+                current_streak: 2,
+            }
+        );
+
+        // subjects missing from the response are still listed
+        assert_eq!(leeches[0].characters, None);
+    }
 }
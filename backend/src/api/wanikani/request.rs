@@ -1,36 +1,108 @@
-use std::env;
-
-use askama::Template;
-use axum::{extract::State, http::HeaderMap, response::Html};
-use chrono::{DateTime, Datelike, Duration, SecondsFormat, TimeZone, Utc};
+use axum::{routing::get, Router};
+use chrono::{DateTime, Datelike, SecondsFormat, TimeZone, Utc};
 use chrono_tz::Asia::Tokyo;
 use reqwest::Client;
 use tokio::try_join;
 
-use crate::api::{
-    add_expiry_header,
-    cacheable::{CacheKey, Cacheable},
-    internal_error, HtmlErrorResponse,
+use crate::{
+    api::{
+        cacheable::{
+            earliest_stale_since, expires_after, next_jst_midnight, CacheKey, CacheSource,
+            Cacheable,
+        },
+        earliest_expiry,
+        study_service::{GoalStatus, StudyCounts, StudyService},
+    },
+    config::{credential, Config, WanikaniConfig},
+    state::AppState,
+};
+
+use super::{
+    collection::{fetch_all, fetch_total_count},
+    data::{
+        Assignment, WanikaniData, WanikaniLessonStats, WanikaniReviewStats,
+        WanikaniSummaryResponse, WanikaniUser,
+    },
 };
 
-use super::data::{WanikaniData, WanikaniReviewStats, WanikaniSummaryResponse};
+mod leeches;
+mod progress;
+
+const WANIKANI_API_URL: &str = "https://api.wanikani.com/v2";
 
-pub async fn wanikani_handler(
-    State(redis_client): State<Option<redis::Client>>,
-) -> Result<(HeaderMap, Html<String>), HtmlErrorResponse> {
-    let ((summary_response, summary_expiry_time), (stats_response, stats_expiry_time)) = try_join!(
-        WanikaniSummaryResponse::get(&redis_client),
-        WanikaniReviewStats::get(&redis_client)
-    )
-    .map_err(internal_error)?;
+impl StudyService for WanikaniData {
+    const ID: &'static str = "wanikani";
+    const DISPLAY_NAME: &'static str = "WaniKani";
 
-    let wanikani_data = WanikaniData::new(summary_response, stats_response);
+    fn sources() -> Vec<CacheSource> {
+        vec![
+            CacheSource::of::<WanikaniSummaryResponse>(),
+            CacheSource::of::<WanikaniReviewStats>(),
+            CacheSource::of::<WanikaniLessonStats>(),
+            CacheSource::of::<WanikaniUser>(),
+        ]
+    }
+
+    fn extra_routes(router: Router<AppState>) -> Router<AppState> {
+        router
+            .route("/wanikani/progress", get(progress::progress_handler))
+            .route(
+                "/api/v1/wanikani/progress",
+                get(progress::progress_json_handler),
+            )
+            .route("/wanikani/leeches", get(leeches::leeches_handler))
+            .route(
+                "/api/v1/wanikani/leeches",
+                get(leeches::leeches_json_handler),
+            )
+    }
 
-    let headers = add_expiry_header(HeaderMap::new(), &[summary_expiry_time, stats_expiry_time]);
+    async fn fetch(state: &AppState) -> anyhow::Result<(Self, Option<DateTime<Utc>>)> {
+        let (summary, stats, lesson_stats, user) = try_join!(
+            WanikaniSummaryResponse::get(state),
+            WanikaniReviewStats::get(state),
+            WanikaniLessonStats::get(state),
+            WanikaniUser::get(state)
+        )?;
+
+        let mut wanikani_data = WanikaniData::new(
+            summary.data,
+            stats.data,
+            lesson_stats.data,
+            user.data,
+            state.config.wanikani.daily_goal,
+            today_jst_midnight_in_utc(None),
+        );
+        wanikani_data.stale_since = earliest_stale_since(&[
+            summary.stale_since,
+            stats.stale_since,
+            lesson_stats.stale_since,
+            user.stale_since,
+        ]);
+        let expires_at = earliest_expiry(&[
+            summary.expires_at,
+            stats.expires_at,
+            lesson_stats.expires_at,
+            user.expires_at,
+        ]);
+
+        Ok((wanikani_data, expires_at))
+    }
 
-    let html_string = wanikani_data.render().map_err(internal_error)?;
+    fn study_counts(&self) -> StudyCounts {
+        StudyCounts {
+            active_review_count: self.active_review_count,
+            new_item_count: self.active_lesson_count,
+        }
+    }
 
-    Ok((headers, Html(html_string)))
+    fn goal_status(&self) -> GoalStatus {
+        if self.user.goal_suspended() {
+            GoalStatus::Suspended
+        } else {
+            GoalStatus::from(self.daily_study_goal_met)
+        }
+    }
 }
 
 impl Cacheable for WanikaniSummaryResponse {
@@ -38,15 +110,11 @@ impl Cacheable for WanikaniSummaryResponse {
         CacheKey::WanikaniSummary
     }
 
-    fn expires_at() -> DateTime<Utc> {
-        Utc::now() + Duration::hours(1)
-    }
-
-    async fn api_fetch() -> anyhow::Result<Self> {
-        let client = wanikani_client()?;
+    async fn api_fetch(config: &Config) -> anyhow::Result<Self> {
+        let client = wanikani_client(&config.wanikani)?;
 
         client
-            .get("https://api.wanikani.com/v2/summary")
+            .get(format!("{WANIKANI_API_URL}/summary"))
             .send()
             .await?
             .error_for_status()?
@@ -54,6 +122,10 @@ impl Cacheable for WanikaniSummaryResponse {
             .await
             .map(|body| Self::try_from_response_body(&body))?
     }
+
+    fn expires_at(&self, config: &Config) -> DateTime<Utc> {
+        expires_after(self.next_unlock_at(Utc::now()), config)
+    }
 }
 
 impl Cacheable for WanikaniReviewStats {
@@ -61,18 +133,40 @@ impl Cacheable for WanikaniReviewStats {
         CacheKey::WanikaniStats
     }
 
-    fn expires_at() -> DateTime<Utc> {
-        let one_hour = Duration::hours(1);
+    async fn api_fetch(config: &Config) -> anyhow::Result<Self> {
+        let client = wanikani_client(&config.wanikani)?;
+        let day_start = today_jst_midnight_in_utc(None);
 
-        Utc::now() + one_hour
+        // only the count is needed, so this is a single page whatever it is
+        let url = format!(
+            "{WANIKANI_API_URL}/review_statistics?updated_after={}",
+            day_start.to_rfc3339_opts(SecondsFormat::Millis, true)
+        );
+        let subjects_reviewed = fetch_total_count(&client, &url).await?;
+
+        Ok(Self {
+            day_start,
+            subjects_reviewed,
+        })
+    }
+
+    // reviews done today only reset at midnight, otherwise only change when
+    // studying, which the ttl covers
+    fn expires_at(&self, config: &Config) -> DateTime<Utc> {
+        expires_after(Some(next_jst_midnight(Utc::now())), config)
+    }
+}
+
+impl Cacheable for WanikaniUser {
+    fn cache_key() -> CacheKey {
+        CacheKey::WanikaniUser
     }
 
-    async fn api_fetch() -> anyhow::Result<Self> {
-        let url = stats_api_url(None);
-        let client = wanikani_client()?;
+    async fn api_fetch(config: &Config) -> anyhow::Result<Self> {
+        let client = wanikani_client(&config.wanikani)?;
 
         client
-            .get(url)
+            .get(format!("{WANIKANI_API_URL}/user"))
             .send()
             .await?
             .error_for_status()?
@@ -82,17 +176,33 @@ impl Cacheable for WanikaniReviewStats {
     }
 }
 
-fn stats_api_url(from_date: Option<DateTime<Utc>>) -> String {
-    let cutoff_date = today_jst_midnight_in_utc(from_date);
+impl Cacheable for WanikaniLessonStats {
+    fn cache_key() -> CacheKey {
+        CacheKey::WanikaniLessonStats
+    }
+
+    async fn api_fetch(config: &Config) -> anyhow::Result<Self> {
+        let client = wanikani_client(&config.wanikani)?;
+        let day_start = today_jst_midnight_in_utc(None);
+
+        // starting a lesson updates the assignment, so anything started today
+        // has been updated since midnight
+        let url = format!(
+            "{WANIKANI_API_URL}/assignments?started=true&updated_after={}",
+            day_start.to_rfc3339_opts(SecondsFormat::Millis, true)
+        );
+        let assignments: Vec<Assignment> = fetch_all(&client, &url).await?;
 
-    format!(
-        "https://api.wanikani.com/v2/review_statistics?updated_after={}",
-        cutoff_date.to_rfc3339_opts(SecondsFormat::Millis, true)
-    )
+        Ok(Self::new(day_start, &assignments))
+    }
+
+    fn expires_at(&self, config: &Config) -> DateTime<Utc> {
+        expires_after(Some(next_jst_midnight(Utc::now())), config)
+    }
 }
 
-fn wanikani_client() -> anyhow::Result<reqwest::Client> {
-    let api_token = env::var("WANIKANI_API_TOKEN")?;
+fn wanikani_client(config: &WanikaniConfig) -> anyhow::Result<reqwest::Client> {
+    let api_token = credential(&config.api_token, "WaniKani API token")?;
 
     let mut headers = reqwest::header::HeaderMap::new();
     headers.insert("Wanikani-Revision", "20170710".parse().unwrap());
@@ -122,19 +232,17 @@ mod test_super {
     #[test]
     fn test_returns_todays_jst_date_in_utc() {
         let from_date = Utc.with_ymd_and_hms(2023, 7, 15, 15, 2, 15).unwrap();
-        let url = stats_api_url(Some(from_date));
 
         assert_eq!(
-            url,
-            "https://api.wanikani.com/v2/review_statistics?updated_after=2023-07-15T15:00:00.000Z"
+            today_jst_midnight_in_utc(Some(from_date)),
+            Utc.with_ymd_and_hms(2023, 7, 15, 15, 0, 0).unwrap()
         );
 
         let from_date = Utc.with_ymd_and_hms(2023, 7, 15, 14, 58, 15).unwrap();
-        let url = stats_api_url(Some(from_date));
 
         assert_eq!(
-            url,
-            "https://api.wanikani.com/v2/review_statistics?updated_after=2023-07-14T15:00:00.000Z"
+            today_jst_midnight_in_utc(Some(from_date)),
+            Utc.with_ymd_and_hms(2023, 7, 14, 15, 0, 0).unwrap()
         );
     }
 }
@@ -0,0 +1,126 @@
+use askama::Template;
+use axum::{extract::State, http::HeaderMap, response::Html, Json};
+
+use crate::{
+    api::{
+        add_expiry_header,
+        cacheable::{CacheKey, Cacheable},
+        internal_error, json_internal_error,
+        wanikani::{
+            collection::fetch_all_resources,
+            data::{WanikaniLeeches, WanikaniReviewStatistics, WanikaniSubjects},
+        },
+        ApiResponse, HtmlErrorResponse, JsonErrorResponse,
+    },
+    config::Config,
+    state::AppState,
+};
+
+use super::{wanikani_client, WANIKANI_API_URL};
+
+impl Cacheable for WanikaniReviewStatistics {
+    fn cache_key() -> CacheKey {
+        CacheKey::WanikaniReviewStatistics
+    }
+
+    const INCREMENTAL: bool = true;
+
+    async fn api_fetch(config: &Config) -> anyhow::Result<Self> {
+        sync_review_statistics(config, Self::default()).await
+    }
+
+    async fn api_update(state: &AppState, previous: Option<Self>) -> anyhow::Result<Self> {
+        sync_review_statistics(&state.config, previous.unwrap_or_default()).await
+    }
+}
+
+async fn sync_review_statistics(
+    config: &Config,
+    review_statistics: WanikaniReviewStatistics,
+) -> anyhow::Result<WanikaniReviewStatistics> {
+    let client = wanikani_client(&config.wanikani)?;
+
+    let statistics = review_statistics
+        .statistics
+        .sync(&client, &format!("{WANIKANI_API_URL}/review_statistics"))
+        .await?;
+
+    Ok(WanikaniReviewStatistics { statistics })
+}
+
+impl Cacheable for WanikaniLeeches {
+    fn cache_key() -> CacheKey {
+        CacheKey::WanikaniLeeches
+    }
+
+    async fn api_fetch(config: &Config) -> anyhow::Result<Self> {
+        let review_statistics =
+            sync_review_statistics(config, WanikaniReviewStatistics::default()).await?;
+
+        build_leeches(config, &review_statistics).await
+    }
+
+    /// Builds on the cached review statistics, synced under their own key,
+    /// so only the leeches' subjects are fetched
+    async fn api_update(state: &AppState, _previous: Option<Self>) -> anyhow::Result<Self> {
+        let review_statistics = WanikaniReviewStatistics::get(state).await?;
+
+        build_leeches(&state.config, &review_statistics.data).await
+    }
+}
+
+async fn build_leeches(
+    config: &Config,
+    review_stats: &WanikaniReviewStatistics,
+) -> anyhow::Result<WanikaniLeeches> {
+    let subject_ids = WanikaniLeeches::subject_ids(review_stats);
+
+    let subjects = match subject_ids.is_empty() {
+        // without ids the endpoint would return every subject
+        true => WanikaniSubjects::default(),
+        false => {
+            let client = wanikani_client(&config.wanikani)?;
+            let ids: Vec<String> = subject_ids.iter().map(u32::to_string).collect();
+            let url = format!("{WANIKANI_API_URL}/subjects?ids={}", ids.join(","));
+
+            WanikaniSubjects::new(fetch_all_resources(&client, &url).await?)
+        }
+    };
+
+    Ok(WanikaniLeeches::new(review_stats, &subjects))
+}
+
+pub async fn leeches_handler(
+    State(state): State<AppState>,
+) -> Result<(HeaderMap, Html<String>), HtmlErrorResponse> {
+    let cached = WanikaniLeeches::get(&state).await.map_err(internal_error)?;
+
+    let mut leeches = cached.data;
+    leeches.stale_since = cached.stale_since;
+
+    let headers = add_expiry_header(HeaderMap::new(), &[cached.expires_at]);
+    let html_string = leeches.render().map_err(internal_error)?;
+
+    Ok((headers, Html(html_string)))
+}
+
+pub async fn leeches_json_handler(
+    State(state): State<AppState>,
+) -> Result<(HeaderMap, Json<ApiResponse<WanikaniLeeches>>), JsonErrorResponse> {
+    let cached = WanikaniLeeches::get(&state)
+        .await
+        .map_err(json_internal_error)?;
+
+    let mut leeches = cached.data;
+    leeches.stale_since = cached.stale_since;
+
+    let headers = add_expiry_header(HeaderMap::new(), &[cached.expires_at]);
+
+    Ok((
+        headers,
+        Json(ApiResponse {
+            data: leeches,
+            expires_at: cached.expires_at,
+        }),
+    ))
+}
@@ -0,0 +1,158 @@
+use anyhow::anyhow;
+use askama::Template;
+use axum::{extract::State, http::HeaderMap, response::Html, Json};
+use tokio::try_join;
+
+use crate::{
+    api::{
+        add_expiry_header,
+        cacheable::{CacheKey, Cacheable},
+        internal_error, json_internal_error,
+        wanikani::collection::{fetch_all, fetch_total_count},
+        wanikani::data::{
+            current_level, Assignment, LevelProgression, LevelSubjectCounts, WanikaniAssignments,
+            WanikaniLevelProgress,
+        },
+        ApiResponse, HtmlErrorResponse, JsonErrorResponse,
+    },
+    config::Config,
+    state::AppState,
+};
+
+use super::{wanikani_client, WANIKANI_API_URL};
+
+impl Cacheable for WanikaniLevelProgress {
+    fn cache_key() -> CacheKey {
+        CacheKey::WanikaniLevelProgress
+    }
+
+    async fn api_fetch(config: &Config) -> anyhow::Result<Self> {
+        let started_assignments = sync_assignments(config, WanikaniAssignments::default()).await?;
+
+        level_progress(config, started_assignments).await
+    }
+
+    /// Syncs the started assignments from the cache, rather than fetching
+    /// all of them every time the progress expires
+    async fn api_update(state: &AppState, _previous: Option<Self>) -> anyhow::Result<Self> {
+        let _guard = state
+            .single_flight
+            .acquire(WanikaniAssignments::cache_key())
+            .await;
+        let (started_assignments, _) = WanikaniAssignments::refresh(state).await?;
+
+        level_progress(&state.config, started_assignments).await
+    }
+}
+
+impl Cacheable for WanikaniAssignments {
+    fn cache_key() -> CacheKey {
+        CacheKey::WanikaniAssignments
+    }
+
+    const INCREMENTAL: bool = true;
+
+    async fn api_fetch(config: &Config) -> anyhow::Result<Self> {
+        sync_assignments(config, Self::default()).await
+    }
+
+    async fn api_update(state: &AppState, previous: Option<Self>) -> anyhow::Result<Self> {
+        sync_assignments(&state.config, previous.unwrap_or_default()).await
+    }
+}
+
+async fn sync_assignments(
+    config: &Config,
+    started_assignments: WanikaniAssignments,
+) -> anyhow::Result<WanikaniAssignments> {
+    let client = wanikani_client(&config.wanikani)?;
+
+    // hidden assignments are kept so ones hidden since the last sync are
+    // updated, they're skipped when counting
+    let assignments = started_assignments
+        .assignments
+        .sync(
+            &client,
+            &format!("{WANIKANI_API_URL}/assignments?started=true"),
+        )
+        .await?;
+
+    Ok(WanikaniAssignments { assignments })
+}
+
+async fn level_progress(
+    config: &Config,
+    started_assignments: WanikaniAssignments,
+) -> anyhow::Result<WanikaniLevelProgress> {
+    let client = wanikani_client(&config.wanikani)?;
+
+    let progressions: Vec<LevelProgression> =
+        fetch_all(&client, &format!("{WANIKANI_API_URL}/level_progressions")).await?;
+    let current_level =
+        current_level(&progressions).ok_or(anyhow!("No WaniKani level progressions found"))?;
+    let level = current_level.level();
+
+    let level_assignments_url = format!(
+        "{WANIKANI_API_URL}/assignments?levels={level}&subject_types=radical,kanji&hidden=false"
+    );
+    let radicals_url =
+        format!("{WANIKANI_API_URL}/subjects?levels={level}&types=radical&hidden=false");
+    let kanji_url = format!("{WANIKANI_API_URL}/subjects?levels={level}&types=kanji&hidden=false");
+
+    let (level_assignments, radicals, kanji) = try_join!(
+        fetch_all::<Assignment>(&client, &level_assignments_url),
+        fetch_total_count(&client, &radicals_url),
+        fetch_total_count(&client, &kanji_url),
+    )?;
+
+    let started_assignments: Vec<Assignment> = started_assignments
+        .assignments
+        .items
+        .into_values()
+        .map(|resource| resource.data)
+        .collect();
+
+    Ok(WanikaniLevelProgress::new(
+        current_level,
+        &level_assignments,
+        LevelSubjectCounts { radicals, kanji },
+        &started_assignments,
+    ))
+}
+
+pub async fn progress_handler(
+    State(state): State<AppState>,
+) -> Result<(HeaderMap, Html<String>), HtmlErrorResponse> {
+    let cached = WanikaniLevelProgress::get(&state)
+        .await
+        .map_err(internal_error)?;
+
+    let mut progress = cached.data;
+    progress.stale_since = cached.stale_since;
+
+    let headers = add_expiry_header(HeaderMap::new(), &[cached.expires_at]);
+    let html_string = progress.render().map_err(internal_error)?;
+
+    Ok((headers, Html(html_string)))
+}
+
+pub async fn progress_json_handler(
+    State(state): State<AppState>,
+) -> Result<(HeaderMap, Json<ApiResponse<WanikaniLevelProgress>>), JsonErrorResponse> {
+    let cached = WanikaniLevelProgress::get(&state)
+        .await
+        .map_err(json_internal_error)?;
+
+    let mut progress = cached.data;
+    progress.stale_since = cached.stale_since;
+
+    let headers = add_expiry_header(HeaderMap::new(), &[cached.expires_at]);
+
+    Ok((
+        headers,
+        Json(ApiResponse {
+            data: progress,
+            expires_at: cached.expires_at,
+        }),
+    ))
+}
@@ -0,0 +1,693 @@
+use std::{env, fs, path::Path, str::FromStr};
+
+use anyhow::{anyhow, Context};
+use chrono::Duration;
+
+use crate::api::registry::ServiceId;
+
+const DEFAULT_CONFIG_PATH: &str = "config.toml";
+
+/// Application configuration, loaded from a TOML file and then overridden by
+/// any of the environment variables the app has historically used
+#[derive(serde::Deserialize, Default, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub server: ServerConfig,
+    pub cache: CacheConfig,
+    pub services: ServicesConfig,
+    pub scheduler: SchedulerConfig,
+    pub wanikani: WanikaniConfig,
+    pub bunpro: BunproConfig,
+    pub satori: SatoriConfig,
+    pub anki: AnkiConfig,
+}
+
+#[derive(serde::Deserialize, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerConfig {
+    pub bind_address: String,
+    /// Minimum time between manual refreshes of a service
+    pub refresh_cooldown_seconds: i64,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            bind_address: String::from("0.0.0.0:3000"),
+            refresh_cooldown_seconds: 60,
+        }
+    }
+}
+
+impl ServerConfig {
+    pub fn refresh_cooldown(&self) -> Duration {
+        Duration::seconds(self.refresh_cooldown_seconds)
+    }
+}
+
+#[derive(serde::Deserialize, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct CacheConfig {
+    /// Defaults to redis when `redis_url` is set, otherwise in-memory
+    pub backend: Option<CacheBackendKind>,
+    pub redis_url: Option<String>,
+    pub ttl_seconds: i64,
+    /// How long a value is kept, and served as stale, after its ttl has passed
+    pub stale_retention_seconds: i64,
+    /// Also lock refreshes through the cache backend, so instances sharing a
+    /// redis only fetch each value once between them
+    pub distributed_lock: bool,
+    /// How long a refresh holds the lock before other callers fetch anyway
+    pub lock_timeout_seconds: i64,
+}
+
+impl Default for CacheConfig {
+    fn default() -> Self {
+        Self {
+            backend: None,
+            redis_url: None,
+            ttl_seconds: 60 * 60,
+            stale_retention_seconds: 60 * 60 * 24,
+            distributed_lock: false,
+            lock_timeout_seconds: 30,
+        }
+    }
+}
+
+#[derive(serde::Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum CacheBackendKind {
+    Redis,
+    Memory,
+    None,
+}
+
+impl FromStr for CacheBackendKind {
+    type Err = anyhow::Error;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.trim().to_lowercase().as_str() {
+            "redis" => Ok(Self::Redis),
+            "memory" => Ok(Self::Memory),
+            "none" => Ok(Self::None),
+            unknown => Err(anyhow!("Unknown cache backend: {unknown}")),
+        }
+    }
+}
+
+impl CacheConfig {
+    pub fn backend(&self) -> CacheBackendKind {
+        match (self.backend, &self.redis_url) {
+            (Some(backend), _) => backend,
+            (None, Some(_)) => CacheBackendKind::Redis,
+            (None, None) => CacheBackendKind::Memory,
+        }
+    }
+
+    pub fn ttl(&self) -> Duration {
+        Duration::seconds(self.ttl_seconds)
+    }
+
+    pub fn stale_retention(&self) -> Duration {
+        Duration::seconds(self.stale_retention_seconds)
+    }
+
+    pub fn lock_timeout(&self) -> Duration {
+        Duration::seconds(self.lock_timeout_seconds)
+    }
+}
+
+#[derive(serde::Deserialize, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServicesConfig {
+    pub enabled: Vec<ServiceId>,
+}
+
+impl Default for ServicesConfig {
+    fn default() -> Self {
+        Self {
+            enabled: ServiceId::ALL.to_vec(),
+        }
+    }
+}
+
+/// Background refreshing of each service's cache, so page loads don't have
+/// to wait on the upstream apis
+#[derive(serde::Deserialize, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct SchedulerConfig {
+    pub enabled: bool,
+    /// Upper bound of the random delay added to each run
+    pub jitter_seconds: i64,
+    pub wanikani: ScheduleConfig,
+    pub bunpro: ScheduleConfig,
+    pub satori: ScheduleConfig,
+    pub anki: ScheduleConfig,
+}
+
+impl Default for SchedulerConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            jitter_seconds: 30,
+            wanikani: ScheduleConfig::default(),
+            bunpro: ScheduleConfig::default(),
+            satori: ScheduleConfig {
+                every_minutes: 30,
+                ..ScheduleConfig::default()
+            },
+            anki: ScheduleConfig::default(),
+        }
+    }
+}
+
+impl SchedulerConfig {
+    pub fn jitter(&self) -> Duration {
+        Duration::seconds(self.jitter_seconds)
+    }
+
+    pub fn schedule_for(&self, service_id: ServiceId) -> &ScheduleConfig {
+        match service_id {
+            ServiceId::Wanikani => &self.wanikani,
+            ServiceId::Bunpro => &self.bunpro,
+            ServiceId::Satori => &self.satori,
+            ServiceId::Anki => &self.anki,
+        }
+    }
+}
+
+/// Runs are aligned to multiples of `every_minutes` from midnight UTC, so 60
+/// runs on the hour and 30 on the hour and half hour
+#[derive(serde::Deserialize, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct ScheduleConfig {
+    pub enabled: bool,
+    pub every_minutes: i64,
+}
+
+impl Default for ScheduleConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            every_minutes: 60,
+        }
+    }
+}
+
+impl ScheduleConfig {
+    pub fn every(&self) -> Duration {
+        Duration::minutes(self.every_minutes)
+    }
+}
+
+#[derive(serde::Deserialize, Default, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct WanikaniConfig {
+    pub api_token: Option<String>,
+    pub daily_goal: WanikaniDailyGoal,
+}
+
+/// What counts as meeting the WaniKani daily goal. There's no review count
+/// goal, as the API only has per subject statistics and not single reviews
+#[derive(serde::Deserialize, serde::Serialize, Clone, Copy, Debug, PartialEq, Default)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+pub enum WanikaniDailyGoal {
+    /// Lessons started since JST midnight
+    Lessons { count: u32 },
+    /// No reviews left in the queue
+    #[default]
+    QueueCleared,
+}
+
+#[derive(serde::Deserialize, Default, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct BunproConfig {
+    pub api_token: Option<String>,
+    pub grammar_cookie: Option<String>,
+    pub goal_decks: BunproGoalDecks,
+}
+
+/// Which Bunpro decks need reviewing for the daily goal to be met
+#[derive(serde::Deserialize, serde::Serialize, Clone, Copy, Debug, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum BunproGoalDecks {
+    Grammar,
+    Vocab,
+    /// Reviews in either deck
+    #[default]
+    Any,
+    /// Reviews in both decks
+    All,
+}
+
+#[derive(serde::Deserialize, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct SatoriConfig {
+    pub cookie: Option<String>,
+    /// Heat level, from 1 to 4, that counts as meeting the daily goal
+    pub target_heat_level: u32,
+}
+
+impl Default for SatoriConfig {
+    fn default() -> Self {
+        Self {
+            cookie: None,
+            target_heat_level: 4,
+        }
+    }
+}
+
+#[derive(serde::Deserialize, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct AnkiConfig {
+    pub cookie: Option<String>,
+    pub deck_name: String,
+}
+
+impl Default for AnkiConfig {
+    fn default() -> Self {
+        Self {
+            cookie: None,
+            deck_name: String::from("Japanese"),
+        }
+    }
+}
+
+impl Config {
+    /// Loads the file at `CONFIG_FILE` (or `config.toml` if it exists),
+    /// applies environment overrides and validates the result
+    pub fn load() -> anyhow::Result<Self> {
+        let config_path = env::var("CONFIG_FILE").ok();
+
+        let mut config = match &config_path {
+            Some(path) => Self::from_file(Path::new(path))?,
+            None if Path::new(DEFAULT_CONFIG_PATH).exists() => {
+                Self::from_file(Path::new(DEFAULT_CONFIG_PATH))?
+            }
+            None => Self::default(),
+        };
+
+        config.apply_overrides(|key| env::var(key).ok())?;
+        config.validate()?;
+
+        Ok(config)
+    }
+
+    fn from_file(path: &Path) -> anyhow::Result<Self> {
+        let contents = fs::read_to_string(path)
+            .with_context(|| format!("Could not read config file {}", path.display()))?;
+
+        Self::from_toml(&contents)
+            .with_context(|| format!("Could not parse config file {}", path.display()))
+    }
+
+    fn from_toml(contents: &str) -> anyhow::Result<Self> {
+        Ok(toml::from_str(contents)?)
+    }
+
+    fn apply_overrides<F>(&mut self, lookup: F) -> anyhow::Result<()>
+    where
+        F: Fn(&str) -> Option<String>,
+    {
+        if let Some(bind_address) = lookup("BIND_ADDRESS") {
+            self.server.bind_address = bind_address;
+        }
+        if let Some(redis_url) = lookup("REDIS_URL") {
+            self.cache.redis_url = Some(redis_url);
+        }
+        if let Some(backend) = lookup("CACHE_BACKEND") {
+            self.cache.backend = Some(backend.parse()?);
+        }
+        if let Some(enabled_services) = lookup("ENABLED_SERVICES") {
+            self.services.enabled = parse_service_ids(&enabled_services)?;
+        }
+        if let Some(api_token) = lookup("WANIKANI_API_TOKEN") {
+            self.wanikani.api_token = Some(api_token);
+        }
+        if let Some(api_token) = lookup("BUNPRO_API_TOKEN") {
+            self.bunpro.api_token = Some(api_token);
+        }
+        if let Some(grammar_cookie) = lookup("BUNPRO_GRAMMAR_COOKIE") {
+            self.bunpro.grammar_cookie = Some(grammar_cookie);
+        }
+        if let Some(cookie) = lookup("SATORI_COOKIE") {
+            self.satori.cookie = Some(cookie);
+        }
+        if let Some(cookie) = lookup("ANKIWEB_COOKIE") {
+            self.anki.cookie = Some(cookie);
+        }
+        if let Some(deck_name) = lookup("ANKI_DECK_NAME") {
+            self.anki.deck_name = deck_name;
+        }
+
+        Ok(())
+    }
+
+    /// Checks every enabled service has its credentials set, reporting all
+    /// of the missing values at once
+    fn validate(&self) -> anyhow::Result<()> {
+        let mut missing = vec![];
+
+        for service in &self.services.enabled {
+            let credentials: &[(&str, &str, &Option<String>)] = match service {
+                ServiceId::Wanikani => &[(
+                    "wanikani.api_token",
+                    "WANIKANI_API_TOKEN",
+                    &self.wanikani.api_token,
+                )],
+                ServiceId::Bunpro => &[
+                    (
+                        "bunpro.api_token",
+                        "BUNPRO_API_TOKEN",
+                        &self.bunpro.api_token,
+                    ),
+                    (
+                        "bunpro.grammar_cookie",
+                        "BUNPRO_GRAMMAR_COOKIE",
+                        &self.bunpro.grammar_cookie,
+                    ),
+                ],
+                ServiceId::Satori => &[("satori.cookie", "SATORI_COOKIE", &self.satori.cookie)],
+                ServiceId::Anki => &[("anki.cookie", "ANKIWEB_COOKIE", &self.anki.cookie)],
+            };
+
+            missing.extend(
+                credentials
+                    .iter()
+                    .filter(|(_, _, value)| value.as_deref().is_none_or(str::is_empty))
+                    .map(|(key, env_var, _)| format!("{key} (or {env_var})")),
+            );
+        }
+
+        if !missing.is_empty() {
+            anyhow::bail!(
+                "Missing credentials for enabled services: {}",
+                missing.join(", ")
+            );
+        }
+
+        if self.cache.backend() == CacheBackendKind::Redis && self.cache.redis_url.is_none() {
+            anyhow::bail!("cache.redis_url (or REDIS_URL) is required for the redis cache backend");
+        }
+
+        if let WanikaniDailyGoal::Lessons { count: 0 } = self.wanikani.daily_goal {
+            anyhow::bail!("wanikani.daily_goal count must be greater than 0");
+        }
+
+        if !(1..=4).contains(&self.satori.target_heat_level) {
+            anyhow::bail!("satori.target_heat_level must be between 1 and 4");
+        }
+
+        if self.server.refresh_cooldown_seconds < 0 {
+            anyhow::bail!("server.refresh_cooldown_seconds must not be negative");
+        }
+
+        if self.cache.ttl_seconds <= 0 {
+            anyhow::bail!("cache.ttl_seconds must be greater than 0");
+        }
+
+        if self.cache.stale_retention_seconds < 0 {
+            anyhow::bail!("cache.stale_retention_seconds must not be negative");
+        }
+
+        if self.cache.lock_timeout_seconds <= 0 {
+            anyhow::bail!("cache.lock_timeout_seconds must be greater than 0");
+        }
+
+        if self.scheduler.jitter_seconds < 0 {
+            anyhow::bail!("scheduler.jitter_seconds must not be negative");
+        }
+
+        for service in ServiceId::ALL {
+            if self.scheduler.schedule_for(service).every_minutes <= 0 {
+                anyhow::bail!("scheduler every_minutes must be greater than 0 for {service:?}");
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Returns the credential or an error naming the missing setting. `Config`
+/// is validated on startup so this should only fail for disabled services
+pub fn credential<'a>(value: &'a Option<String>, name: &str) -> anyhow::Result<&'a str> {
+    value
+        .as_deref()
+        .ok_or_else(|| anyhow!("{name} is not configured"))
+}
+
+fn parse_service_ids(enabled_services: &str) -> anyhow::Result<Vec<ServiceId>> {
+    enabled_services
+        .split(',')
+        .filter(|id| !id.trim().is_empty())
+        .map(ServiceId::from_str)
+        .collect()
+}
+
+#[cfg(test)]
+mod test_super {
+    use std::collections::HashMap;
+
+    use super::*;
+
+    fn lookup_from(values: &[(&str, &str)]) -> impl Fn(&str) -> Option<String> {
+        let values: HashMap<String, String> = values
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect();
+
+        move |key| values.get(key).cloned()
+    }
+
+    #[test]
+    fn test_parse_service_ids() {
+        let service_ids = parse_service_ids("wanikani, Anki,").unwrap();
+
+        assert_eq!(service_ids, vec![ServiceId::Wanikani, ServiceId::Anki]);
+    }
+
+    #[test]
+    fn test_parse_service_ids_rejects_unknown_service() {
+        let service_ids = parse_service_ids("wanikani,duolingo");
+
+        assert!(service_ids.is_err());
+    }
+
+    #[test]
+    fn test_from_toml_uses_defaults_for_missing_values() {
+        let config = Config::from_toml(
+            r#"
+            [wanikani]
+            api_token = "token"
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(config.server.bind_address, "0.0.0.0:3000");
+        assert_eq!(config.cache.ttl(), Duration::hours(1));
+        assert_eq!(config.anki.deck_name, "Japanese");
+        assert_eq!(config.services.enabled, ServiceId::ALL.to_vec());
+        assert_eq!(config.wanikani.api_token.as_deref(), Some("token"));
+        assert_eq!(config.wanikani.daily_goal, WanikaniDailyGoal::QueueCleared);
+    }
+
+    #[test]
+    fn test_from_toml_wanikani_daily_goal() {
+        let config = Config::from_toml(
+            r#"
+            [wanikani.daily_goal]
+            type = "lessons"
+            count = 10
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            config.wanikani.daily_goal,
+            WanikaniDailyGoal::Lessons { count: 10 }
+        );
+
+        let config = Config::from_toml(
+            r#"
+            [wanikani]
+            daily_goal = { type = "queue_cleared" }
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(config.wanikani.daily_goal, WanikaniDailyGoal::QueueCleared);
+
+        assert!(Config::from_toml(
+            r#"
+            [wanikani]
+            daily_goal = { type = "reviews", count = 100 }
+            "#,
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_from_toml_bunpro_goal_decks() {
+        assert_eq!(
+            Config::from_toml("").unwrap().bunpro.goal_decks,
+            BunproGoalDecks::Any
+        );
+
+        let config = Config::from_toml(
+            r#"
+            [bunpro]
+            goal_decks = "grammar"
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(config.bunpro.goal_decks, BunproGoalDecks::Grammar);
+    }
+
+    #[test]
+    fn test_from_toml_scheduler_defaults() {
+        let config = Config::from_toml(
+            r#"
+            [scheduler]
+            enabled = true
+
+            [scheduler.anki]
+            enabled = false
+            "#,
+        )
+        .unwrap();
+
+        assert!(config.scheduler.enabled);
+        assert_eq!(config.scheduler.wanikani.every(), Duration::hours(1));
+        assert_eq!(config.scheduler.satori.every(), Duration::minutes(30));
+        assert!(!config.scheduler.schedule_for(ServiceId::Anki).enabled);
+    }
+
+    #[test]
+    fn test_cache_backend_defaults_from_redis_url() {
+        let mut config = Config::default();
+
+        assert_eq!(config.cache.backend(), CacheBackendKind::Memory);
+
+        config
+            .apply_overrides(lookup_from(&[("REDIS_URL", "redis://localhost")]))
+            .unwrap();
+
+        assert_eq!(config.cache.backend(), CacheBackendKind::Redis);
+
+        config
+            .apply_overrides(lookup_from(&[("CACHE_BACKEND", "none")]))
+            .unwrap();
+
+        assert_eq!(config.cache.backend(), CacheBackendKind::None);
+    }
+
+    #[test]
+    fn test_validate_requires_redis_url_for_redis_backend() {
+        let mut config = Config::default();
+        config
+            .apply_overrides(lookup_from(&[
+                ("ENABLED_SERVICES", ""),
+                ("CACHE_BACKEND", "redis"),
+            ]))
+            .unwrap();
+
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn test_validate_satori_target_heat_level() {
+        let config = Config::from_toml(
+            r#"
+            [services]
+            enabled = []
+
+            [satori]
+            target_heat_level = 3
+            "#,
+        )
+        .unwrap();
+
+        assert!(config.validate().is_ok());
+
+        let config = Config::from_toml(
+            r#"
+            [services]
+            enabled = []
+
+            [satori]
+            target_heat_level = 5
+            "#,
+        )
+        .unwrap();
+
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn test_from_toml_rejects_unknown_keys() {
+        let config = Config::from_toml(
+            r#"
+            [wanikani]
+            api_tokn = "token"
+            "#,
+        );
+
+        assert!(config.is_err());
+    }
+
+    #[test]
+    fn test_env_overrides_file_values() {
+        let mut config = Config::from_toml(
+            r#"
+            [services]
+            enabled = ["wanikani", "satori"]
+
+            [wanikani]
+            api_token = "from-file"
+            "#,
+        )
+        .unwrap();
+
+        config
+            .apply_overrides(lookup_from(&[
+                ("WANIKANI_API_TOKEN", "from-env"),
+                ("ENABLED_SERVICES", "wanikani"),
+            ]))
+            .unwrap();
+
+        assert_eq!(config.wanikani.api_token.as_deref(), Some("from-env"));
+        assert_eq!(config.services.enabled, vec![ServiceId::Wanikani]);
+    }
+
+    #[test]
+    fn test_validate_lists_missing_credentials_for_enabled_services() {
+        let mut config = Config::default();
+        config
+            .apply_overrides(lookup_from(&[
+                ("ENABLED_SERVICES", "bunpro,anki"),
+                ("BUNPRO_API_TOKEN", "token"),
+            ]))
+            .unwrap();
+
+        let error = config.validate().unwrap_err().to_string();
+
+        assert_eq!(
+            error,
+            "Missing credentials for enabled services: bunpro.grammar_cookie (or BUNPRO_GRAMMAR_COOKIE), anki.cookie (or ANKIWEB_COOKIE)"
+        );
+    }
+
+    #[test]
+    fn test_validate_ignores_disabled_services() {
+        let mut config = Config::default();
+        config
+            .apply_overrides(lookup_from(&[
+                ("ENABLED_SERVICES", "satori"),
+                ("SATORI_COOKIE", "cookie"),
+            ]))
+            .unwrap();
+
+        assert!(config.validate().is_ok());
+    }
+}
@@ -1,15 +1,34 @@
-use std::{env, fs};
-
-use axum::{http::StatusCode, response::Html, routing::get, Router};
+use std::sync::Arc;
+
+use askama::Template;
+use axum::{
+    extract::State,
+    response::Html,
+    routing::{get, post},
+    Router,
+};
 use tokio::{net::TcpListener, signal};
 use tower_http::{services::ServeDir, trace::TraceLayer};
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
 
-use crate::api::{
-    anki::anki_handler, bunpro::bunpro_handler, satori::satori_handler, wanikani::wanikani_handler,
+use crate::{
+    api::{
+        cacheable::{backend::CacheBackend, single_flight::SingleFlight},
+        internal_error,
+        refresh::{refresh_all_handler, RefreshLimiter},
+        registry::Registry,
+        summary::{summary_handler, summary_json_handler},
+        HtmlErrorResponse,
+    },
+    config::Config,
+    state::AppState,
+    templates::Dashboard,
 };
 
 pub mod api;
+pub mod config;
+pub mod scheduler;
+pub mod state;
 pub mod templates;
 
 #[tokio::main]
@@ -22,18 +41,36 @@ async fn main() {
         .with(tracing_subscriber::fmt::layer())
         .init();
 
-    let redis_client = get_redis_connection();
+    let config = Config::load().expect("Invalid configuration");
+    let registry = Registry::new(&config.services.enabled);
+    let bind_address = config.server.bind_address.clone();
+
+    rustls::crypto::aws_lc_rs::default_provider()
+        .install_default()
+        .expect("Failed to install rustls crypto provider");
+
+    tracing::info!("using {:?} cache backend", config.cache.backend());
+
+    let state = AppState {
+        cache: CacheBackend::from_config(&config.cache).expect("Invalid cache configuration"),
+        single_flight: SingleFlight::default(),
+        refresh_limiter: RefreshLimiter::default(),
+        registry: Arc::new(registry),
+        config: Arc::new(config),
+    };
+
+    scheduler::spawn(&state);
 
     let app = Router::new()
         .merge(Router::new().nest_service("/assets", ServeDir::new("dist/assets")))
         .route("/", get(root_handler))
-        .route("/wanikani", get(wanikani_handler))
-        .route("/bunpro", get(bunpro_handler))
-        .route("/satori", get(satori_handler))
-        .route("/anki", get(anki_handler))
-        .with_state(redis_client)
+        .route("/summary", get(summary_handler))
+        .route("/api/v1/summary", get(summary_json_handler))
+        .route("/refresh", post(refresh_all_handler))
+        .merge(state.registry.routes())
+        .with_state(state)
         .layer(TraceLayer::new_for_http());
-    let listener = TcpListener::bind("0.0.0.0:3000").await.unwrap();
+    let listener = TcpListener::bind(&bind_address).await.unwrap();
 
     tracing::info!("listening on {}", listener.local_addr().unwrap());
 
@@ -43,13 +80,12 @@ async fn main() {
         .unwrap();
 }
 
-async fn root_handler() -> Result<Html<String>, (StatusCode, &'static str)> {
-    let html_string = fs::read_to_string("./dist/index.html").map_err(|_| {
-        (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            "htmx file could not be found",
-        )
-    })?;
+async fn root_handler(State(state): State<AppState>) -> Result<Html<String>, HtmlErrorResponse> {
+    let dashboard = Dashboard {
+        services: state.registry.services(),
+    };
+
+    let html_string = dashboard.render().map_err(internal_error)?;
 
     Ok(Html(html_string))
 }
@@ -76,15 +112,3 @@ async fn shutdown_signal() {
 
     tracing::info!("signal received, starting graceful shutdown");
 }
-
-fn get_redis_connection() -> Option<redis::Client> {
-    rustls::crypto::aws_lc_rs::default_provider()
-        .install_default()
-        .expect("Failed to install rustls crypto provider");
-
-    let redis_client: anyhow::Result<redis::Client> = env::var("REDIS_URL")
-        .map_err(Into::into)
-        .and_then(|redis_url| Ok(redis::Client::open(redis_url)?));
-
-    redis_client.ok()
-}
@@ -0,0 +1,144 @@
+use chrono::{DateTime, Duration, Utc};
+use rand::Rng;
+
+use crate::{api::cacheable::CacheSource, state::AppState};
+
+/// Spawns a refresh loop for every enabled service that has scheduling
+/// turned on. Each loop refreshes the service's cache sources once on
+/// startup and then on the service's cadence
+pub fn spawn(state: &AppState) {
+    let scheduler_config = &state.config.scheduler;
+
+    if !scheduler_config.enabled {
+        tracing::info!("scheduler disabled, caches will be filled on request");
+        return;
+    }
+
+    for service in state.registry.services() {
+        let schedule = scheduler_config.schedule_for(service.service_id);
+
+        if !schedule.enabled {
+            tracing::info!("scheduled refresh disabled for {}", service.id);
+            continue;
+        }
+
+        tracing::info!(
+            "scheduling refresh of {} every {} minutes",
+            service.id,
+            schedule.every_minutes
+        );
+
+        tokio::spawn(refresh_loop(
+            state.clone(),
+            service.id,
+            (service.sources)(),
+            schedule.every(),
+            scheduler_config.jitter(),
+        ));
+    }
+}
+
+async fn refresh_loop(
+    state: AppState,
+    service_id: &'static str,
+    sources: Vec<CacheSource>,
+    every: Duration,
+    jitter: Duration,
+) {
+    loop {
+        refresh_sources(&state, service_id, &sources).await;
+
+        let now = Utc::now();
+        let next_run = next_run(now, every, random_jitter(jitter));
+
+        tracing::debug!("next refresh of {} at {}", service_id, next_run);
+
+        tokio::time::sleep((next_run - now).to_std().unwrap_or_default()).await;
+    }
+}
+
+async fn refresh_sources(state: &AppState, service_id: &str, sources: &[CacheSource]) {
+    for source in sources {
+        let started_at = Utc::now();
+
+        match source.refresh(state.clone()).await {
+            Ok(()) => tracing::info!(
+                "refreshed {} ({}) in {}ms",
+                source.cache_key,
+                service_id,
+                (Utc::now() - started_at).num_milliseconds()
+            ),
+            Err(err) => tracing::warn!(
+                "failed to refresh {} ({}): {:#}",
+                source.cache_key,
+                service_id,
+                err
+            ),
+        }
+    }
+}
+
+/// The next multiple of `every` since the unix epoch after `now`, pushed back
+/// by `jitter`
+fn next_run(now: DateTime<Utc>, every: Duration, jitter: Duration) -> DateTime<Utc> {
+    let every_seconds = every.num_seconds().max(1);
+    let timestamp = now.timestamp();
+    let next_timestamp = timestamp - timestamp.rem_euclid(every_seconds) + every_seconds;
+
+    DateTime::from_timestamp(next_timestamp, 0).unwrap_or(now + every) + jitter
+}
+
+fn random_jitter(max_jitter: Duration) -> Duration {
+    let max_seconds = max_jitter.num_seconds();
+
+    if max_seconds <= 0 {
+        return Duration::zero();
+    }
+
+    Duration::seconds(rand::rng().random_range(0..=max_seconds))
+}
+
+#[cfg(test)]
+mod test_super {
+    use chrono::TimeZone;
+
+    use super::*;
+
+    #[test]
+    fn test_next_run_aligns_to_the_hour() {
+        let now = Utc.with_ymd_and_hms(2024, 6, 21, 10, 12, 30).unwrap();
+
+        let next = next_run(now, Duration::hours(1), Duration::zero());
+
+        assert_eq!(next, Utc.with_ymd_and_hms(2024, 6, 21, 11, 0, 0).unwrap());
+    }
+
+    #[test]
+    fn test_next_run_aligns_to_the_half_hour() {
+        let now = Utc.with_ymd_and_hms(2024, 6, 21, 10, 12, 30).unwrap();
+
+        let next = next_run(now, Duration::minutes(30), Duration::zero());
+
+        assert_eq!(next, Utc.with_ymd_and_hms(2024, 6, 21, 10, 30, 0).unwrap());
+    }
+
+    #[test]
+    fn test_next_run_on_boundary_moves_to_next_interval() {
+        let now = Utc.with_ymd_and_hms(2024, 6, 21, 11, 0, 0).unwrap();
+
+        let next = next_run(now, Duration::hours(1), Duration::seconds(20));
+
+        assert_eq!(next, Utc.with_ymd_and_hms(2024, 6, 21, 12, 0, 20).unwrap());
+    }
+
+    #[test]
+    fn test_random_jitter_stays_in_range() {
+        for _ in 0..100 {
+            let jitter = random_jitter(Duration::seconds(5));
+
+            assert!(jitter >= Duration::zero() && jitter <= Duration::seconds(5));
+        }
+
+        assert_eq!(random_jitter(Duration::zero()), Duration::zero());
+    }
+}
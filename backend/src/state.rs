@@ -0,0 +1,19 @@
+use std::sync::Arc;
+
+use crate::{
+    api::{
+        cacheable::{backend::CacheBackend, single_flight::SingleFlight},
+        refresh::RefreshLimiter,
+        registry::Registry,
+    },
+    config::Config,
+};
+
+#[derive(Clone)]
+pub struct AppState {
+    pub cache: CacheBackend,
+    pub single_flight: SingleFlight,
+    pub refresh_limiter: RefreshLimiter,
+    pub registry: Arc<Registry>,
+    pub config: Arc<Config>,
+}
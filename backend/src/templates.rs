@@ -1 +1,11 @@
+use askama::Template;
+
+use crate::api::registry::ServiceEntry;
+
 pub mod utils;
+
+#[derive(Template)]
+#[template(path = "dashboard.html")]
+pub struct Dashboard<'a> {
+    pub services: &'a [ServiceEntry],
+}
@@ -8,6 +8,10 @@ pub fn format_date(date: &DateTime<Utc>) -> String {
     )
 }
 
+pub fn format_hour(date: &DateTime<Utc>) -> String {
+    date.with_timezone(&Tokyo).format("%H:%M").to_string()
+}
+
 #[cfg(test)]
 mod test_super {
     use chrono::TimeZone;